@@ -1,35 +1,155 @@
 use aoc_runner_derive::{aoc, aoc_generator};
 use itertools::Itertools;
+use num_bigint::BigUint;
+use std::collections::HashSet;
 
+/// A pair of delimiters and the scores of a token of this kind.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
-#[repr(u8)]
-pub enum TokenType {
-    Paren,
-    Square,
-    Curly,
-    Angle,
+pub struct TokenType {
+    pub open: char,
+    pub close: char,
+    pub illegal_value: u32,
+    pub completion_value: u32,
 }
 
 impl TokenType {
-    pub fn illegal_value(self) -> u32 {
-        match self {
-            TokenType::Paren => 3,
-            TokenType::Square => 57,
-            TokenType::Curly => 1197,
-            TokenType::Angle => 25137,
+    pub const PAREN: TokenType = TokenType::new('(', ')', 3, 1);
+    pub const SQUARE: TokenType = TokenType::new('[', ']', 57, 2);
+    pub const CURLY: TokenType = TokenType::new('{', '}', 1197, 3);
+    pub const ANGLE: TokenType = TokenType::new('<', '>', 25137, 4);
+
+    pub const fn new(open: char, close: char, illegal_value: u32, completion_value: u32) -> Self {
+        TokenType {
+            open,
+            close,
+            illegal_value,
+            completion_value,
         }
     }
 
+    pub fn illegal_value(self) -> u32 {
+        self.illegal_value
+    }
+
     pub fn completion_value(self) -> u32 {
-        match self {
-            TokenType::Paren => 1,
-            TokenType::Square => 2,
-            TokenType::Curly => 3,
-            TokenType::Angle => 4,
+        self.completion_value
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct UnknownCharacter {
+    pub index: usize,
+    pub ch: char,
+}
+
+impl std::fmt::Display for UnknownCharacter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "unexpected character '{}' at column {}",
+            self.ch,
+            self.index + 1
+        )
+    }
+}
+
+impl std::error::Error for UnknownCharacter {}
+
+/// A character used by more than one delimiter, or to both open and close.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct DuplicateDelimiter {
+    pub ch: char,
+}
+
+impl std::fmt::Display for DuplicateDelimiter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "delimiter '{}' is used more than once", self.ch)
+    }
+}
+
+impl std::error::Error for DuplicateDelimiter {}
+
+/// The delimiters of a bracket language, and what to do with any other character.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BracketSet {
+    pairs: Vec<TokenType>,
+    ignore_unknown: bool,
+}
+
+impl Default for BracketSet {
+    fn default() -> Self {
+        BracketSet {
+            pairs: vec![
+                TokenType::PAREN,
+                TokenType::SQUARE,
+                TokenType::CURLY,
+                TokenType::ANGLE,
+            ],
+            ignore_unknown: false,
         }
     }
 }
 
+impl BracketSet {
+    pub fn new(pairs: Vec<TokenType>) -> Result<Self, DuplicateDelimiter> {
+        let mut seen = HashSet::new();
+        for ch in pairs.iter().flat_map(|p| [p.open, p.close]) {
+            if !seen.insert(ch) {
+                return Err(DuplicateDelimiter { ch });
+            }
+        }
+        Ok(BracketSet {
+            pairs,
+            ignore_unknown: false,
+        })
+    }
+
+    pub fn ignoring_unknown(mut self) -> Self {
+        self.ignore_unknown = true;
+        self
+    }
+
+    pub fn pairs(&self) -> &[TokenType] {
+        &self.pairs
+    }
+
+    pub fn ignores_unknown(&self) -> bool {
+        self.ignore_unknown
+    }
+
+    pub fn token(&self, ch: char) -> Option<Token> {
+        self.pairs.iter().find_map(|&ty| {
+            if ch == ty.open {
+                Some(Token { ty, is_open: true })
+            } else if ch == ty.close {
+                Some(Token { ty, is_open: false })
+            } else {
+                None
+            }
+        })
+    }
+
+    pub fn parse_line(&self, input: &str) -> Result<Line, UnknownCharacter> {
+        let mut tokens = vec![];
+        let mut columns = vec![];
+        for (index, ch) in input.chars().enumerate() {
+            match self.token(ch) {
+                Some(tok) => {
+                    tokens.push(tok);
+                    columns.push(index);
+                }
+                None if self.ignore_unknown => (),
+                None => return Err(UnknownCharacter { index, ch }),
+            }
+        }
+        Ok(Line {
+            tokens,
+            columns,
+            source: input.to_string(),
+        })
+    }
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub struct Token {
     ty: TokenType,
@@ -37,118 +157,570 @@ pub struct Token {
 }
 
 impl Token {
+    /// A token of the default bracket set.
     pub fn from_char(ch: char) -> Token {
-        match ch {
-            '(' => Token {
-                ty: TokenType::Paren,
-                is_open: true,
-            },
-            ')' => Token {
-                ty: TokenType::Paren,
-                is_open: false,
-            },
-            '[' => Token {
-                ty: TokenType::Square,
-                is_open: true,
-            },
-            ']' => Token {
-                ty: TokenType::Square,
-                is_open: false,
-            },
-            '{' => Token {
-                ty: TokenType::Curly,
-                is_open: true,
-            },
-            '}' => Token {
-                ty: TokenType::Curly,
-                is_open: false,
-            },
-            '<' => Token {
-                ty: TokenType::Angle,
-                is_open: true,
-            },
-            '>' => Token {
-                ty: TokenType::Angle,
-                is_open: false,
-            },
+        let ty = match ch {
+            '(' | ')' => TokenType::PAREN,
+            '[' | ']' => TokenType::SQUARE,
+            '{' | '}' => TokenType::CURLY,
+            '<' | '>' => TokenType::ANGLE,
             _ => panic!("Unexpected character!"),
+        };
+        Token {
+            ty,
+            is_open: ch == ty.open,
+        }
+    }
+
+    pub fn to_char(self) -> char {
+        if self.is_open {
+            self.ty.open
+        } else {
+            self.ty.close
         }
     }
 
     pub fn is_match(&self, other: Token) -> bool {
         self.ty == other.ty && self.is_open != other.is_open
     }
+
+    pub fn closer(self) -> Token {
+        Token {
+            ty: self.ty,
+            is_open: false,
+        }
+    }
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_char())
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LineStatus {
+    Valid,
+    /// `found` at `index` does not close the innermost open chunk, which needed `expected`
+    /// (or nothing was open at all).
+    Corrupted {
+        index: usize,
+        expected: Option<Token>,
+        found: Token,
+    },
+    /// Every chunk is closed correctly, but `missing` are needed to close the open ones.
+    Incomplete {
+        missing: Vec<Token>,
+    },
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Completion {
+    pub closers: String,
+    /// Unbounded, since the score grows by a factor of 5 per closer and overflows a `u64`
+    /// after 27 of them.
+    pub score: BigUint,
+}
+
+impl Completion {
+    fn from_tokens(tokens: &[Token]) -> Self {
+        let mut score = BigUint::from(0u32);
+        for tok in tokens {
+            score = score * 5u32 + tok.ty.completion_value();
+        }
+        Completion {
+            closers: tokens.iter().map(|t| t.to_char()).collect(),
+            score,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CompletionError {
+    Corrupted { index: usize, found: Token },
 }
 
+impl std::fmt::Display for CompletionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompletionError::Corrupted { index, found } => write!(
+                f,
+                "line is corrupted by `{}` at column {}",
+                found,
+                index + 1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CompletionError {}
+
 pub struct Line {
     tokens: Vec<Token>,
+    // column of each token in `source`, which may contain ignored characters
+    columns: Vec<usize>,
+    source: String,
 }
 
 impl Line {
-    pub fn illegal(&self) -> u32 {
+    pub fn check(&self) -> LineStatus {
         let mut stack = vec![];
-        for tok in &self.tokens {
+        for (index, &tok) in self.tokens.iter().enumerate() {
             if tok.is_open {
                 stack.push(tok);
             } else {
-                let other = stack.pop();
-                match other {
-                    Some(other_token) if tok.is_match(*other_token) => (),
-                    _ => return tok.ty.illegal_value(),
+                match stack.pop() {
+                    Some(other_token) if tok.is_match(other_token) => (),
+                    other => {
+                        return LineStatus::Corrupted {
+                            index: self.columns[index],
+                            expected: other.map(Token::closer),
+                            found: tok,
+                        }
+                    }
                 }
             }
         }
-        0
+
+        if stack.is_empty() {
+            LineStatus::Valid
+        } else {
+            LineStatus::Incomplete {
+                missing: stack.iter().rev().map(|t| t.closer()).collect(),
+            }
+        }
+    }
+
+    pub fn illegal(&self) -> u32 {
+        match self.check() {
+            LineStatus::Corrupted { found, .. } => found.ty.illegal_value(),
+            _ => 0,
+        }
     }
 
     pub fn is_illegal(&self) -> bool {
-        let mut stack = vec![];
-        for tok in &self.tokens {
-            if tok.is_open {
-                stack.push(tok);
-            } else {
-                let other = stack.pop();
-                match other {
-                    Some(other_token) if tok.is_match(*other_token) => (),
-                    _ => return true,
-                }
+        matches!(self.check(), LineStatus::Corrupted { .. })
+    }
+
+    /// The closing characters that complete the line, and their score.
+    pub fn autocomplete(&self) -> Result<Completion, CompletionError> {
+        match self.check() {
+            LineStatus::Valid => Ok(Completion::from_tokens(&[])),
+            LineStatus::Incomplete { missing } => Ok(Completion::from_tokens(&missing)),
+            LineStatus::Corrupted { index, found, .. } => {
+                Err(CompletionError::Corrupted { index, found })
             }
         }
-        false
     }
 
-    pub fn autocomplete(&self) -> u64 {
-        let mut stack = vec![];
-        for tok in &self.tokens {
-            if tok.is_open {
-                stack.push(tok);
-            } else {
-                stack.pop();
+    /// Builds the chunk tree of the line, repairing it with the fewest possible token
+    /// insertions and deletions. Every repair is reported, not just the first error.
+    ///
+    /// Runs in O(n³) time and O(n²) memory for n tokens.
+    pub fn parse(&self) -> SyntaxTree {
+        #[derive(Copy, Clone)]
+        enum Choice {
+            Empty,
+            // the first token is repaired: an opener gets a closer inserted at the end of
+            // the range, a closer is deleted
+            Repair,
+            // the first token is closed by the token at this index
+            Match(usize),
+        }
+
+        let n = self.tokens.len();
+        let mut cost = vec![vec![0usize; n + 1]; n + 1];
+        let mut choice = vec![vec![Choice::Empty; n + 1]; n + 1];
+        for len in 1..=n {
+            for i in 0..=n - len {
+                let j = i + len;
+                let first = self.tokens[i];
+                // on ties leave openers unclosed rather than borrowing a later closer, as
+                // a stack-based parser would
+                let mut best = (1 + cost[i + 1][j], Choice::Repair);
+                if first.is_open {
+                    for k in i + 1..j {
+                        if first.is_match(self.tokens[k]) {
+                            let c = cost[i + 1][k] + cost[k + 1][j];
+                            if c < best.0 {
+                                best = (c, Choice::Match(k));
+                            }
+                        }
+                    }
+                }
+                cost[i][j] = best.0;
+                choice[i][j] = best.1;
+            }
+        }
+
+        let end = self.source.chars().count();
+        let column = |k: usize| self.columns.get(k).copied().unwrap_or(end);
+        let mut repairs = vec![];
+        fn build(
+            line: &Line,
+            choice: &[Vec<Choice>],
+            column: &dyn Fn(usize) -> usize,
+            mut i: usize,
+            j: usize,
+            repairs: &mut Vec<Repair>,
+        ) -> Vec<Chunk> {
+            let mut chunks = vec![];
+            while i < j {
+                let tok = line.tokens[i];
+                match choice[i][j] {
+                    Choice::Empty => break,
+                    Choice::Match(k) => {
+                        chunks.push(Chunk {
+                            ty: tok.ty,
+                            open: column(i),
+                            close: Some(column(k)),
+                            children: build(line, choice, column, i + 1, k, repairs),
+                        });
+                        i = k + 1;
+                    }
+                    Choice::Repair if tok.is_open => {
+                        let children = build(line, choice, column, i + 1, j, repairs);
+                        repairs.push(Repair::Insert {
+                            column: column(j),
+                            token: tok.closer(),
+                        });
+                        chunks.push(Chunk {
+                            ty: tok.ty,
+                            open: column(i),
+                            close: None,
+                            children,
+                        });
+                        i = j;
+                    }
+                    Choice::Repair => {
+                        repairs.push(Repair::Delete {
+                            column: column(i),
+                            token: tok,
+                        });
+                        i += 1;
+                    }
+                }
             }
+            chunks
         }
+        let chunks = build(self, &choice, &column, 0, n, &mut repairs);
+        repairs.sort_by_key(|r| match r {
+            Repair::Insert { column, .. } | Repair::Delete { column, .. } => *column,
+        });
 
-        let mut score = 0;
-        while !stack.is_empty() {
-            score *= 5;
-            let tok = stack.pop().unwrap();
-            score += tok.ty.completion_value() as u64;
+        SyntaxTree { chunks, repairs }
+    }
+
+    /// A compiler-style error message with a caret under the offending column, or `None`
+    /// for a valid line. `line_number` is 1-based.
+    pub fn diagnostic(&self, line_number: usize) -> Option<String> {
+        let (message, column, label) = match self.check() {
+            LineStatus::Valid => return None,
+            LineStatus::Corrupted {
+                index,
+                expected: Some(expected),
+                found,
+            } => (
+                format!("expected `{}`, found `{}`", expected, found),
+                index,
+                format!("expected `{}`", expected),
+            ),
+            LineStatus::Corrupted {
+                index,
+                expected: None,
+                found,
+            } => (
+                format!("unexpected closing `{}`", found),
+                index,
+                "nothing to close".to_string(),
+            ),
+            LineStatus::Incomplete { missing } => {
+                let missing = missing.iter().map(|t| t.to_char()).collect::<String>();
+                (
+                    "unclosed chunks at end of line".to_string(),
+                    self.source.chars().count(),
+                    format!("missing `{}`", missing),
+                )
+            }
+        };
+
+        let gutter = " ".repeat(line_number.to_string().len());
+        Some(format!(
+            "error: {}\n{}--> line {}, column {}\n{} |\n{} | {}\n{} | {}^ {}\n",
+            message,
+            gutter,
+            line_number,
+            column + 1,
+            gutter,
+            line_number,
+            self,
+            gutter,
+            " ".repeat(column),
+            label
+        ))
+    }
+}
+
+/// A matched pair of delimiters and everything between them.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Chunk {
+    pub ty: TokenType,
+    pub open: usize,
+    /// Column of the closing delimiter, or `None` if it had to be inserted
+    pub close: Option<usize>,
+    pub children: Vec<Chunk>,
+}
+
+impl std::fmt::Display for Chunk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.ty.open)?;
+        for child in &self.children {
+            write!(f, "{}", child)?;
         }
-        score
+        write!(f, "{}", self.ty.close)
+    }
+}
+
+/// One edit made to repair a line. Columns refer to the original line; an insertion goes
+/// before the character at its column.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Repair {
+    Insert { column: usize, token: Token },
+    Delete { column: usize, token: Token },
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SyntaxTree {
+    pub chunks: Vec<Chunk>,
+    pub repairs: Vec<Repair>,
+}
+
+impl SyntaxTree {
+    pub fn is_valid(&self) -> bool {
+        self.repairs.is_empty()
+    }
+
+    /// The repaired line, without any ignored characters.
+    pub fn repaired(&self) -> String {
+        self.chunks.iter().map(|c| c.to_string()).collect()
+    }
+}
+
+impl std::fmt::Display for Line {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
     }
 }
 
 impl From<Vec<Token>> for Line {
     fn from(tokens: Vec<Token>) -> Self {
-        Line { tokens }
+        Line {
+            columns: (0..tokens.len()).collect(),
+            source: tokens.iter().map(|t| t.to_char()).collect(),
+            tokens,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum StreamError {
+    UnknownCharacter { line: usize, index: usize, ch: char },
+    InvalidUtf8 { offset: usize },
+}
+
+impl std::fmt::Display for StreamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StreamError::UnknownCharacter { line, index, ch } => write!(
+                f,
+                "unexpected character '{}' at line {}, column {}",
+                ch,
+                line + 1,
+                index + 1
+            ),
+            StreamError::InvalidUtf8 { offset } => {
+                write!(f, "invalid UTF-8 at byte offset {}", offset)
+            }
+        }
+    }
+}
+
+impl std::error::Error for StreamError {}
+
+/// Everything a `StreamValidator` knows, so it can be stored and resumed later.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Snapshot {
+    pub set: BracketSet,
+    /// 0-based line and column of the next character
+    pub line: usize,
+    pub column: usize,
+    pub stack: Vec<Token>,
+    pub corruption: Option<LineStatus>,
+    /// Bytes of a UTF-8 sequence split across chunks
+    pub pending: Vec<u8>,
+    /// Bytes of the characters accepted so far, not counting `pending`
+    pub bytes_read: usize,
+    pub syntax_error_score: u64,
+    pub completion_scores: Vec<BigUint>,
+}
+
+/// Push-based validator for a navigation subsystem of any length. Only the open chunks of
+/// the current line are kept, plus the running scores of the lines finished so far.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StreamValidator {
+    state: Snapshot,
+}
+
+impl StreamValidator {
+    pub fn new(set: BracketSet) -> Self {
+        StreamValidator {
+            state: Snapshot {
+                set,
+                line: 0,
+                column: 0,
+                stack: vec![],
+                corruption: None,
+                pending: vec![],
+                bytes_read: 0,
+                syntax_error_score: 0,
+                completion_scores: vec![],
+            },
+        }
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        self.state.clone()
+    }
+
+    pub fn resume(snapshot: Snapshot) -> Self {
+        StreamValidator { state: snapshot }
+    }
+
+    /// Leaves the state unchanged if `ch` is rejected.
+    pub fn push_char(&mut self, ch: char) -> Result<(), StreamError> {
+        if ch == '\n' || ch == '\r' {
+            if ch == '\n' {
+                self.finish_line();
+            }
+            self.state.bytes_read += 1;
+            return Ok(());
+        }
+
+        let state = &mut self.state;
+        let tok = state.set.token(ch);
+        if tok.is_none() && !state.set.ignore_unknown {
+            return Err(StreamError::UnknownCharacter {
+                line: state.line,
+                index: state.column,
+                ch,
+            });
+        }
+
+        let index = state.column;
+        state.column += 1;
+        state.bytes_read += ch.len_utf8();
+        let tok = match tok {
+            // the rest of a corrupted line doesn't matter
+            Some(tok) if state.corruption.is_none() => tok,
+            _ => return Ok(()),
+        };
+
+        if tok.is_open {
+            state.stack.push(tok);
+        } else {
+            match state.stack.pop() {
+                Some(other) if tok.is_match(other) => (),
+                other => {
+                    state.corruption = Some(LineStatus::Corrupted {
+                        index,
+                        expected: other.map(Token::closer),
+                        found: tok,
+                    });
+                    state.stack.clear();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn push_str(&mut self, input: &str) -> Result<(), StreamError> {
+        input.chars().try_for_each(|ch| self.push_char(ch))
+    }
+
+    /// Accepts arbitrary chunks of UTF-8, which may split a character between calls. On
+    /// error, every character before the offending one has been pushed and the rest of the
+    /// chunk is dropped.
+    pub fn push_bytes(&mut self, bytes: &[u8]) -> Result<(), StreamError> {
+        let mut buffer = std::mem::take(&mut self.state.pending);
+        buffer.extend_from_slice(bytes);
+
+        let (valid_len, invalid) = match std::str::from_utf8(&buffer) {
+            Ok(_) => (buffer.len(), false),
+            Err(e) => (e.valid_up_to(), e.error_len().is_some()),
+        };
+        let (valid, rest) = buffer.split_at(valid_len);
+        self.push_str(std::str::from_utf8(valid).unwrap())?;
+        if invalid {
+            return Err(StreamError::InvalidUtf8 {
+                offset: self.state.bytes_read,
+            });
+        }
+        self.state.pending = rest.to_vec();
+        Ok(())
+    }
+
+    /// Status of the line currently being read.
+    pub fn status(&self) -> LineStatus {
+        if let Some(corruption) = &self.state.corruption {
+            corruption.clone()
+        } else if self.state.stack.is_empty() {
+            LineStatus::Valid
+        } else {
+            LineStatus::Incomplete {
+                missing: self.state.stack.iter().rev().map(|t| t.closer()).collect(),
+            }
+        }
+    }
+
+    fn finish_line(&mut self) {
+        match self.status() {
+            LineStatus::Corrupted { found, .. } => {
+                self.state.syntax_error_score += found.ty.illegal_value() as u64
+            }
+            LineStatus::Incomplete { missing } => self
+                .state
+                .completion_scores
+                .push(Completion::from_tokens(&missing).score),
+            LineStatus::Valid => (),
+        }
+        self.state.line += 1;
+        self.state.column = 0;
+        self.state.stack.clear();
+        self.state.corruption = None;
+    }
+
+    /// Ends the stream, finishing the last line if it had no trailing newline.
+    pub fn finish(mut self) -> Snapshot {
+        if self.state.column > 0 {
+            self.finish_line();
+        }
+        self.state
+    }
+}
+
+impl Snapshot {
+    /// The part 2 answer: the middle completion score.
+    pub fn middle_completion_score(&self) -> Option<BigUint> {
+        let mut scores = self.completion_scores.clone();
+        scores.sort_unstable();
+        scores.get(scores.len() / 2).cloned()
     }
 }
 
 #[aoc_generator(day10)]
-pub fn input_generator_day10(input: &str) -> Vec<Line> {
-    input
-        .split('\n')
-        .map(|s| s.chars().map(Token::from_char).collect_vec().into())
-        .collect()
+pub fn input_generator_day10(input: &str) -> Result<Vec<Line>, UnknownCharacter> {
+    let set = BracketSet::default();
+    input.split('\n').map(|s| set.parse_line(s)).collect()
 }
 
 #[aoc(day10, part1)]
@@ -157,14 +729,14 @@ pub fn solve_day10_part1(input: &[Line]) -> u32 {
 }
 
 #[aoc(day10, part2)]
-pub fn solve_day10_part2(input: &[Line]) -> u64 {
+pub fn solve_day10_part2(input: &[Line]) -> BigUint {
     let mut scores = input
         .iter()
-        .filter(|l| !l.is_illegal())
-        .map(|l| l.autocomplete())
+        .filter_map(|l| l.autocomplete().ok())
+        .map(|c| c.score)
         .collect_vec();
     scores.sort_unstable();
-    scores[scores.len() / 2]
+    scores[scores.len() / 2].clone()
 }
 
 #[cfg(test)]
@@ -185,15 +757,315 @@ mod tests {
 
     #[test]
     fn test_day10_part1() {
-        let data = input_generator_day10(&DATA);
+        let data = input_generator_day10(DATA).unwrap();
         let result = solve_day10_part1(&data);
         assert_eq!(result, 26397);
     }
 
     #[test]
     fn test_day10_part2() {
-        let data = input_generator_day10(&DATA);
+        let data = input_generator_day10(DATA).unwrap();
         let result = solve_day10_part2(&data);
-        assert_eq!(result, 288957);
+        assert_eq!(result, BigUint::from(288957u32));
+    }
+
+    #[test]
+    fn test_autocomplete() {
+        let data = input_generator_day10(DATA).unwrap();
+        let completion = data[0].autocomplete().unwrap();
+        assert_eq!(completion.closers, "}}]])})]");
+        assert_eq!(completion.score, BigUint::from(288957u32));
+        assert_eq!(
+            data[2].autocomplete(),
+            Err(CompletionError::Corrupted {
+                index: 12,
+                found: Token::from_char('}')
+            })
+        );
+        let valid = input_generator_day10("<>").unwrap();
+        assert_eq!(valid[0].autocomplete().unwrap().closers, "");
+    }
+
+    #[test]
+    fn test_custom_bracket_set() {
+        let set = BracketSet::new(vec![
+            TokenType::new('(', ')', 1, 1),
+            TokenType::new('«', '»', 10, 2),
+            TokenType::new('b', 'e', 100, 3),
+        ])
+        .unwrap();
+        let lines = ["b(«»)e", "b(«)»e", "b(«", "b(x)e"];
+        let parsed = lines.iter().map(|l| set.parse_line(l)).collect_vec();
+        assert_eq!(parsed[0].as_ref().unwrap().check(), LineStatus::Valid);
+        assert_eq!(parsed[1].as_ref().unwrap().illegal(), 1);
+        let completion = parsed[2].as_ref().unwrap().autocomplete().unwrap();
+        assert_eq!(completion.closers, "»)e");
+        assert_eq!(completion.score, BigUint::from((2u32 * 5 + 1) * 5 + 3));
+        assert_eq!(
+            parsed[3].as_ref().err(),
+            Some(&UnknownCharacter { index: 2, ch: 'x' })
+        );
+    }
+
+    #[test]
+    fn test_ignore_unknown() {
+        let set = BracketSet::default().ignoring_unknown();
+        let line = set.parse_line("fn main() { let x = [1, 2); }").unwrap();
+        assert_eq!(
+            line.check(),
+            LineStatus::Corrupted {
+                index: 25,
+                expected: Some(Token::from_char(']')),
+                found: Token::from_char(')'),
+            }
+        );
+        assert!(line.diagnostic(1).unwrap().ends_with(
+            "1 | fn main() { let x = [1, 2); }\n  |                          ^ expected `]`\n"
+        ));
+        assert!(BracketSet::default().parse_line("a()").is_err());
+    }
+
+    #[test]
+    fn test_bracket_set_rejects_duplicates() {
+        assert_eq!(
+            BracketSet::new(vec![TokenType::PAREN, TokenType::new(')', '(', 1, 1)]),
+            Err(DuplicateDelimiter { ch: ')' })
+        );
+        assert_eq!(
+            BracketSet::new(vec![TokenType::new('|', '|', 1, 1)]),
+            Err(DuplicateDelimiter { ch: '|' })
+        );
+    }
+
+    #[test]
+    fn test_parse_valid() {
+        let line = &input_generator_day10("[<>({}){}]()").unwrap()[0];
+        let tree = line.parse();
+        assert!(tree.is_valid());
+        assert_eq!(tree.chunks.len(), 2);
+        assert_eq!(tree.chunks[0].ty, TokenType::SQUARE);
+        assert_eq!(tree.chunks[0].close, Some(9));
+        assert_eq!(tree.chunks[0].children.len(), 3);
+        assert_eq!(tree.chunks[0].children[1].children[0].open, 4);
+        assert_eq!(tree.repaired(), "[<>({}){}]()");
+    }
+
+    #[test]
+    fn test_parse_reports_all_errors() {
+        // two stray closers, each fixed by one deletion
+        let line = &input_generator_day10("(]<>)[}]").unwrap()[0];
+        let tree = line.parse();
+        assert_eq!(
+            tree.repairs,
+            vec![
+                Repair::Delete {
+                    column: 1,
+                    token: Token::from_char(']')
+                },
+                Repair::Delete {
+                    column: 6,
+                    token: Token::from_char('}')
+                },
+            ]
+        );
+        assert_eq!(tree.repaired(), "(<>)[]");
+    }
+
+    #[test]
+    fn test_parse_incomplete() {
+        let line = &input_generator_day10("[({(<(())[]>[[{[]{<()<>>").unwrap()[0];
+        let tree = line.parse();
+        let inserted = tree
+            .repairs
+            .iter()
+            .map(|r| match r {
+                Repair::Insert { column, token } => {
+                    assert_eq!(*column, 24);
+                    token.to_char()
+                }
+                Repair::Delete { .. } => panic!("unexpected deletion"),
+            })
+            .collect::<String>();
+        assert_eq!(inserted, line.autocomplete().unwrap().closers);
+        assert_eq!(tree.repaired(), "[({(<(())[]>[[{[]{<()<>>}}]])})]");
+    }
+
+    #[test]
+    fn test_parse_corrupted_examples() {
+        let data = input_generator_day10(DATA).unwrap();
+        for line in data.iter().filter(|l| l.is_illegal()) {
+            let tree = line.parse();
+            assert!(!tree.is_valid());
+            let repaired = input_generator_day10(&tree.repaired()).unwrap();
+            assert_eq!(repaired[0].check(), LineStatus::Valid);
+        }
+    }
+
+    #[test]
+    fn test_stream_validator() {
+        let mut validator = StreamValidator::new(BracketSet::default());
+        validator.push_str(DATA).unwrap();
+        let result = validator.finish();
+        assert_eq!(result.line, 10);
+        assert_eq!(result.syntax_error_score, 26397);
+        assert_eq!(result.completion_scores.len(), 5);
+        assert_eq!(
+            result.middle_completion_score(),
+            Some(BigUint::from(288957u32))
+        );
+    }
+
+    #[test]
+    fn test_stream_validator_status() {
+        let mut validator = StreamValidator::new(BracketSet::default());
+        validator.push_str("[({").unwrap();
+        assert_eq!(
+            validator.status(),
+            LineStatus::Incomplete {
+                missing: "})]".chars().map(Token::from_char).collect()
+            }
+        );
+        validator.push_str("})]").unwrap();
+        assert_eq!(validator.status(), LineStatus::Valid);
+        validator.push_str("<}").unwrap();
+        assert_eq!(
+            validator.status(),
+            LineStatus::Corrupted {
+                index: 7,
+                expected: Some(Token::from_char('>')),
+                found: Token::from_char('}'),
+            }
+        );
+        assert_eq!(
+            validator.push_str("\n(a"),
+            Err(StreamError::UnknownCharacter {
+                line: 1,
+                index: 1,
+                ch: 'a'
+            })
+        );
+    }
+
+    #[test]
+    fn test_stream_validator_chunks_and_resume() {
+        let set = BracketSet::new(vec![TokenType::PAREN, TokenType::new('«', '»', 5, 7)]).unwrap();
+        let input = "(«(»\n««()".as_bytes();
+
+        let mut whole = StreamValidator::new(set.clone());
+        whole.push_bytes(input).unwrap();
+        let expected = whole.finish();
+        assert_eq!(expected.syntax_error_score, 5);
+        assert_eq!(
+            expected.completion_scores,
+            vec![BigUint::from(7u32 * 5 + 7)]
+        );
+
+        // feed one byte at a time, snapshotting and resuming between bytes
+        let mut snapshot = StreamValidator::new(set).snapshot();
+        for b in input {
+            let mut validator = StreamValidator::resume(snapshot);
+            validator.push_bytes(&[*b]).unwrap();
+            snapshot = validator.snapshot();
+        }
+        assert_eq!(StreamValidator::resume(snapshot).finish(), expected);
+
+        let mut validator = StreamValidator::new(BracketSet::default());
+        validator.push_bytes(b"<").unwrap();
+        assert_eq!(
+            validator.push_bytes(&[b'(', 0xff, b')']),
+            Err(StreamError::InvalidUtf8 { offset: 2 })
+        );
+        assert_eq!(
+            validator.status(),
+            LineStatus::Incomplete {
+                missing: ")>".chars().map(Token::from_char).collect()
+            }
+        );
+    }
+
+    #[test]
+    fn test_stream_validator_unknown_after_corruption() {
+        let mut validator = StreamValidator::new(BracketSet::default());
+        validator.push_str("(]").unwrap();
+        let before = validator.snapshot();
+        assert_eq!(
+            validator.push_char('x'),
+            Err(StreamError::UnknownCharacter {
+                line: 0,
+                index: 2,
+                ch: 'x'
+            })
+        );
+        assert_eq!(validator.snapshot(), before);
+        assert!(BracketSet::default().parse_line("(]x").is_err());
+
+        let mut validator = StreamValidator::new(BracketSet::default().ignoring_unknown());
+        validator.push_str("(]x)\n").unwrap();
+        assert_eq!(validator.finish().syntax_error_score, 57);
+    }
+
+    #[test]
+    fn test_autocomplete_long_line() {
+        // 40 `>` closers score 4 * (5^40 - 1) / (5 - 1) = 5^40 - 1, well past u64::MAX
+        let line = input_generator_day10(&"<".repeat(40)).unwrap();
+        let completion = line[0].autocomplete().unwrap();
+        assert_eq!(completion.closers, ">".repeat(40));
+        assert_eq!(completion.score, BigUint::from(5u32).pow(40) - 1u32);
+        assert!(completion.score > BigUint::from(u64::MAX));
+    }
+
+    #[test]
+    fn test_check() {
+        let data = input_generator_day10(DATA).unwrap();
+        assert_eq!(
+            data[2].check(),
+            LineStatus::Corrupted {
+                index: 12,
+                expected: Some(Token::from_char(']')),
+                found: Token::from_char('}'),
+            }
+        );
+        assert_eq!(
+            data[0].check(),
+            LineStatus::Incomplete {
+                missing: "}}]])})]".chars().map(Token::from_char).collect()
+            }
+        );
+        let valid = input_generator_day10("[<>({}){}[([])<>]]").unwrap();
+        assert_eq!(valid[0].check(), LineStatus::Valid);
+        let unopened = input_generator_day10("()]").unwrap();
+        assert_eq!(
+            unopened[0].check(),
+            LineStatus::Corrupted {
+                index: 2,
+                expected: None,
+                found: Token::from_char(']'),
+            }
+        );
+    }
+
+    #[test]
+    fn test_diagnostic() {
+        let data = input_generator_day10(DATA).unwrap();
+        assert_eq!(
+            data[2].diagnostic(3).unwrap(),
+            "error: expected `]`, found `}`
+ --> line 3, column 13
+  |
+3 | {([(<{}[<>[]}>{[]{[(<()>
+  |             ^ expected `]`
+"
+        );
+        assert_eq!(
+            data[9].diagnostic(10).unwrap(),
+            "error: unclosed chunks at end of line
+  --> line 10, column 25
+   |
+10 | <{([{{}}[<[[[<>{}]]]>[]]
+   |                         ^ missing `])}>`
+"
+        );
+        let valid = input_generator_day10("()").unwrap();
+        assert_eq!(valid[0].diagnostic(1), None);
     }
 }
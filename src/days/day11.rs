@@ -1,18 +1,66 @@
 use aoc_runner_derive::{aoc, aoc_generator};
-use itertools::Itertools;
+use rayon::prelude::*;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
 
-#[derive(Clone, Debug)]
-pub struct Grid {
-    energy: [[u8; 10]; 10],
-    flashes: usize,
+/// Lines and columns are 0-based, but displayed 1-based.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    Empty,
+    UnexpectedChar {
+        line: usize,
+        column: usize,
+        ch: char,
+    },
+    RaggedRow {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
 }
 
-impl Grid {
-    fn is_in_bounds(x: i32, y: i32) -> bool {
-        x >= 0 && y >= 0 && x < 10 && y < 10
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "grid is empty"),
+            ParseError::UnexpectedChar { line, column, ch } => write!(
+                f,
+                "unexpected character {:?} at line {}, column {}",
+                ch,
+                line + 1,
+                column + 1
+            ),
+            ParseError::RaggedRow {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "line {} has {} cells, expected {}",
+                line + 1,
+                found,
+                expected
+            ),
+        }
     }
+}
+
+impl std::error::Error for ParseError {}
 
-    const ADJACENCY: [(i32, i32); 8] = [
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Neighbourhood {
+    /// Orthogonal neighbours only.
+    Four,
+    /// Orthogonal and diagonal neighbours.
+    Eight,
+}
+
+impl Neighbourhood {
+    const FOUR: [(i32, i32); 4] = [(0, -1), (-1, 0), (1, 0), (0, 1)];
+
+    const EIGHT: [(i32, i32); 8] = [
         (-1, -1),
         (0, -1),
         (1, -1),
@@ -23,51 +71,262 @@ impl Grid {
         (1, 1),
     ];
 
-    pub fn simulate(&mut self) {
-        for y in 0..10 {
-            for x in 0..10 {
-                self.energy[y][x] += 1;
+    pub fn offsets(self) -> &'static [(i32, i32)] {
+        match self {
+            Neighbourhood::Four => &Self::FOUR,
+            Neighbourhood::Eight => &Self::EIGHT,
+        }
+    }
+}
+
+/// The rules of the octopus automaton. The default is the puzzle's: every step
+/// adds 1 to each octopus, an octopus above 9 flashes, each flash adds 1 to
+/// its eight neighbours, and the grid edges are walls.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct FlashRules {
+    /// An octopus flashes once its energy exceeds this level.
+    pub threshold: u8,
+    /// Energy every octopus gains at the start of a step.
+    pub step_gain: u8,
+    /// Energy each neighbour gains from a flash.
+    pub flash_gain: u8,
+    pub neighbourhood: Neighbourhood,
+    /// Whether neighbours wrap around the grid edges, making it a torus.
+    pub wrap: bool,
+}
+
+impl Default for FlashRules {
+    fn default() -> Self {
+        FlashRules {
+            threshold: 9,
+            step_gain: 1,
+            flash_gain: 1,
+            neighbourhood: Neighbourhood::Eight,
+            wrap: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Grid {
+    energy: Vec<Vec<u8>>,
+    width: usize,
+    height: usize,
+    flashes: usize,
+    rules: FlashRules,
+}
+
+impl Grid {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> u8 {
+        self.energy[y][x]
+    }
+
+    pub fn rules(&self) -> FlashRules {
+        self.rules
+    }
+
+    pub fn with_rules(mut self, rules: FlashRules) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    fn neighbour(&self, x: usize, y: usize, offset: (i32, i32)) -> Option<(usize, usize)> {
+        neighbour_in(self.width, self.height, self.rules.wrap, x, y, offset)
+    }
+
+    /// Advances one step, returning the cells that flashed in the order they
+    /// flashed. Cells pushed over the threshold by the energy increase have depth
+    /// 0; cells set off by a neighbour's flash are one deeper than that neighbour.
+    pub fn simulate(&mut self) -> Vec<Flash> {
+        let FlashRules {
+            threshold,
+            step_gain,
+            flash_gain,
+            neighbourhood,
+            ..
+        } = self.rules;
+
+        for row in self.energy.iter_mut() {
+            for e in row.iter_mut() {
+                *e = e.saturating_add(step_gain);
             }
         }
 
-        let mut flashing = vec![];
-        for y in 0..10 {
-            for x in 0..10 {
-                if self.energy[y][x] > 9 {
-                    flashing.push((x, y));
+        let mut flashing = VecDeque::new();
+        for (y, row) in self.energy.iter().enumerate() {
+            for (x, &e) in row.iter().enumerate() {
+                if e > threshold {
+                    flashing.push_back(Flash { x, y, depth: 0 });
                 }
             }
         }
 
-        while !flashing.is_empty() {
-            let handle = flashing.pop().unwrap();
+        let mut flashed = vec![];
+        while let Some(flash) = flashing.pop_front() {
             self.flashes += 1;
-            for adj in &Self::ADJACENCY {
-                let ax = handle.0 as i32 + adj.0;
-                let ay = handle.1 as i32 + adj.1;
-                if Self::is_in_bounds(ax, ay) {
-                    let ax = ax as usize;
-                    let ay = ay as usize;
-
-                    if self.energy[ay][ax] > 9 {
+            for &offset in neighbourhood.offsets() {
+                if let Some((ax, ay)) = self.neighbour(flash.x, flash.y, offset) {
+                    if self.energy[ay][ax] > threshold {
                         continue;
                     }
 
-                    self.energy[ay][ax] += 1;
-                    if self.energy[ay][ax] > 9 {
-                        flashing.push((ax, ay));
+                    self.energy[ay][ax] = self.energy[ay][ax].saturating_add(flash_gain);
+                    if self.energy[ay][ax] > threshold {
+                        flashing.push_back(Flash {
+                            x: ax,
+                            y: ay,
+                            depth: flash.depth + 1,
+                        });
                     }
                 }
             }
+            flashed.push(flash);
         }
 
-        for y in 0..10 {
-            for x in 0..10 {
-                if self.energy[y][x] > 9 {
-                    self.energy[y][x] = 0;
+        for row in self.energy.iter_mut() {
+            for e in row.iter_mut() {
+                if *e > threshold {
+                    *e = 0;
                 }
             }
         }
+
+        flashed
+    }
+
+    /// Advances one step like `simulate`, splitting the grid into bands of
+    /// `band_rows` rows that rayon processes in parallel, and returns the number
+    /// of flashes. The cascade proceeds in rounds: the cells that flashed in the
+    /// previous round are kept as one bit per cell, and each 64-cell word counts
+    /// its flashing neighbours with bit-sliced adders over the shifted frontier
+    /// rows before the cells next to the frontier gather their energy.
+    pub fn simulate_parallel(&mut self, band_rows: usize) -> usize {
+        let FlashRules {
+            threshold,
+            step_gain,
+            flash_gain,
+            neighbourhood,
+            wrap,
+        } = self.rules;
+        let (width, height) = (self.width, self.height);
+        let words = width.div_ceil(64);
+        let band_rows = band_rows.max(1);
+        if words == 0 || height == 0 {
+            return 0;
+        }
+
+        let mut frontier = vec![0u64; height * words];
+        self.energy
+            .par_chunks_mut(band_rows)
+            .zip(frontier.par_chunks_mut(band_rows * words))
+            .for_each(|(rows, bits)| {
+                for (row, bits) in rows.iter_mut().zip(bits.chunks_mut(words)) {
+                    for (x, e) in row.iter_mut().enumerate() {
+                        *e = e.saturating_add(step_gain);
+                        if *e > threshold {
+                            bits[x / 64] |= 1 << (x % 64);
+                        }
+                    }
+                }
+            });
+        let mut flashed = frontier.clone();
+        let mut next = vec![0u64; height * words];
+        let mut total = count_bits(&frontier);
+        let mut round_flashes = total;
+
+        while round_flashes > 0 {
+            let active_rows: Vec<bool> = frontier
+                .chunks(words)
+                .map(|row| row.iter().any(|&w| w != 0))
+                .collect();
+            let current = &frontier;
+            let frontier_row = |y: usize| &current[y * words..(y + 1) * words];
+
+            self.energy
+                .par_chunks_mut(band_rows)
+                .zip(flashed.par_chunks_mut(band_rows * words))
+                .zip(next.par_chunks_mut(band_rows * words))
+                .enumerate()
+                .for_each(|(band, ((rows, flashed_rows), next_rows))| {
+                    let rows = rows
+                        .iter_mut()
+                        .zip(flashed_rows.chunks_mut(words))
+                        .zip(next_rows.chunks_mut(words));
+                    for (i, ((row, flashed_row), next_row)) in rows.enumerate() {
+                        let y = band * band_rows + i;
+                        next_row.fill(0);
+                        // frontier rows at dy = -1, 0 and 1
+                        let source_rows = [-1, 0, 1].map(|dy| {
+                            neighbour_in(width, height, wrap, 0, y, (0, dy))
+                                .filter(|&(_, ny)| active_rows[ny])
+                                .map(|(_, ny)| frontier_row(ny))
+                        });
+                        if source_rows.iter().all(Option::is_none) {
+                            continue;
+                        }
+
+                        for w in 0..words {
+                            // per-cell count of flashing neighbours, one bit plane per
+                            // binary digit
+                            let mut planes = [0u64; 4];
+                            let mut any = 0;
+                            for &(dx, dy) in neighbourhood.offsets() {
+                                if let Some(bits) = source_rows[(dy + 1) as usize] {
+                                    let mut carry = shifted_word(bits, w, dx, width, wrap);
+                                    any |= carry;
+                                    for plane in planes.iter_mut() {
+                                        let overflow = *plane & carry;
+                                        *plane ^= carry;
+                                        carry = overflow;
+                                    }
+                                }
+                            }
+
+                            let mut candidates = any & !flashed_row[w];
+                            while candidates != 0 {
+                                let bit = candidates.trailing_zeros();
+                                candidates &= candidates - 1;
+
+                                let count = planes
+                                    .iter()
+                                    .enumerate()
+                                    .map(|(k, plane)| (((plane >> bit) & 1) as u32) << k)
+                                    .sum::<u32>();
+                                let x = w * 64 + bit as usize;
+                                let energy = row[x] as u32 + count * flash_gain as u32;
+                                row[x] = energy.min(u8::MAX as u32) as u8;
+                                if row[x] > threshold {
+                                    next_row[w] |= 1 << bit;
+                                    flashed_row[w] |= 1 << bit;
+                                }
+                            }
+                        }
+                    }
+                });
+
+            round_flashes = count_bits(&next);
+            total += round_flashes;
+            std::mem::swap(&mut frontier, &mut next);
+        }
+
+        self.energy.par_chunks_mut(band_rows).for_each(|rows| {
+            for e in rows.iter_mut().flatten() {
+                if *e > threshold {
+                    *e = 0;
+                }
+            }
+        });
+
+        self.flashes += total;
+        total
     }
 
     pub fn all_flashed(&self) -> bool {
@@ -77,22 +336,311 @@ impl Grid {
     pub fn flashes(&self) -> usize {
         self.flashes
     }
+
+    /// Simulates up to `limit` steps looking for the first step on which every
+    /// octopus flashes, stopping early if the grid revisits an earlier state.
+    /// Only a 64-bit hash of each state is kept; a repeated hash is confirmed by
+    /// re-simulating to the earlier step.
+    pub fn find_sync(&self, limit: usize) -> SyncOutcome {
+        self.find_sync_with(limit, |energy| {
+            let mut hasher = DefaultHasher::new();
+            energy.hash(&mut hasher);
+            hasher.finish()
+        })
+    }
+
+    fn find_sync_with<H>(&self, limit: usize, hash: H) -> SyncOutcome
+    where
+        H: Fn(&[Vec<u8>]) -> u64,
+    {
+        let mut grid = self.clone();
+        // every step seen with each hash, in increasing order
+        let mut seen: HashMap<u64, Vec<usize>> = HashMap::new();
+        seen.insert(hash(&grid.energy), vec![0]);
+
+        for step in 1..=limit {
+            grid.simulate();
+            if grid.all_flashed() {
+                return SyncOutcome::Synchronized(step);
+            }
+            let candidates = seen.entry(hash(&grid.energy)).or_default();
+            let mut earlier = self.clone();
+            let mut earlier_step = 0;
+            for &start in candidates.iter() {
+                while earlier_step < start {
+                    earlier.simulate();
+                    earlier_step += 1;
+                }
+                if earlier.energy == grid.energy {
+                    return SyncOutcome::Cycle {
+                        start,
+                        period: step - start,
+                    };
+                }
+            }
+            candidates.push(step);
+        }
+        SyncOutcome::NotFoundWithin(limit)
+    }
+
+    /// The energy levels as rows of digits, using letters for levels above 9 and
+    /// `#` above 35.
+    pub fn render_ascii(&self) -> String {
+        let mut result = String::new();
+        for row in &self.energy {
+            result.extend(
+                row.iter()
+                    .map(|&e| std::char::from_digit(e as u32, 36).unwrap_or('#')),
+            );
+            result.push('\n');
+        }
+        result
+    }
+
+    /// Writes the energy levels as a binary PPM image with `scale` pixels per cell.
+    pub fn write_ppm<W: Write>(&self, mut writer: W, scale: usize) -> io::Result<()> {
+        write!(
+            writer,
+            "P6\n{} {}\n255\n",
+            self.width * scale,
+            self.height * scale
+        )?;
+        let mut pixels = Vec::with_capacity(self.width * scale * 3);
+        for row in &self.energy {
+            pixels.clear();
+            for &e in row {
+                let (r, g, b) = energy_colour(e, self.rules.threshold);
+                for _ in 0..scale {
+                    pixels.extend_from_slice(&[r, g, b]);
+                }
+            }
+            for _ in 0..scale {
+                writer.write_all(&pixels)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the initial state and the next `steps` steps as text frames, each
+    /// headed by its step number and flash statistics.
+    pub fn write_ascii_frames<W: Write>(&self, steps: usize, mut writer: W) -> io::Result<()> {
+        let mut grid = self.clone();
+        writeln!(writer, "step 0")?;
+        write!(writer, "{}", grid.render_ascii())?;
+        for step in 1..=steps {
+            let flashed = grid.simulate();
+            let depth = flashed.iter().map(|f| f.depth).max();
+            write!(writer, "\nstep {}: {} flashes", step, flashed.len())?;
+            if let Some(depth) = depth {
+                write!(writer, ", cascade depth {}", depth)?;
+            }
+            writeln!(writer)?;
+            write!(writer, "{}", grid.render_ascii())?;
+        }
+        Ok(())
+    }
+
+    /// Writes the initial state and the next `steps` steps as one PPM image per
+    /// step, using `open_frame` to create the writer for each step number.
+    pub fn write_ppm_frames<W, F>(
+        &self,
+        steps: usize,
+        scale: usize,
+        mut open_frame: F,
+    ) -> io::Result<()>
+    where
+        W: Write,
+        F: FnMut(usize) -> io::Result<W>,
+    {
+        let mut grid = self.clone();
+        grid.write_ppm(open_frame(0)?, scale)?;
+        for step in 1..=steps {
+            grid.simulate();
+            grid.write_ppm(open_frame(step)?, scale)?;
+        }
+        Ok(())
+    }
+}
+
+fn neighbour_in(
+    width: usize,
+    height: usize,
+    wrap: bool,
+    x: usize,
+    y: usize,
+    (dx, dy): (i32, i32),
+) -> Option<(usize, usize)> {
+    let nx = x as i64 + dx as i64;
+    let ny = y as i64 + dy as i64;
+    let (width, height) = (width as i64, height as i64);
+    if wrap {
+        Some((
+            nx.rem_euclid(width) as usize,
+            ny.rem_euclid(height) as usize,
+        ))
+    } else if nx >= 0 && ny >= 0 && nx < width && ny < height {
+        Some((nx as usize, ny as usize))
+    } else {
+        None
+    }
+}
+
+fn test_bit(bits: &[u64], x: usize) -> bool {
+    (bits[x / 64] >> (x % 64)) & 1 == 1
+}
+
+fn count_bits(bits: &[u64]) -> usize {
+    bits.iter().map(|w| w.count_ones() as usize).sum()
+}
+
+// Word `w` of the mask of cells `x` in a row for which cell `x + dx` is set in
+// `bits`, a row of `width` cells.
+fn shifted_word(bits: &[u64], w: usize, dx: i32, width: usize, wrap: bool) -> u64 {
+    let last = bits.len() - 1;
+    let tail = width % 64;
+    let valid = if w == last && tail > 0 {
+        (1 << tail) - 1
+    } else {
+        u64::MAX
+    };
+    let word = match dx {
+        -1 => {
+            let carry = if w > 0 {
+                bits[w - 1] >> 63
+            } else if wrap {
+                test_bit(bits, width - 1) as u64
+            } else {
+                0
+            };
+            bits[w] << 1 | carry
+        }
+        1 => {
+            let carry = if w < last {
+                bits[w + 1] << 63
+            } else if wrap {
+                (bits[0] & 1) << ((width - 1) % 64)
+            } else {
+                0
+            };
+            bits[w] >> 1 | carry
+        }
+        _ => bits[w],
+    };
+    word & valid
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Flash {
+    pub x: usize,
+    pub y: usize,
+    pub depth: usize,
+}
+
+// Flashed cells are white; charging cells ramp from dark to bright blue as they
+// approach the flash threshold.
+fn energy_colour(energy: u8, threshold: u8) -> (u8, u8, u8) {
+    if energy == 0 {
+        (255, 255, 255)
+    } else {
+        let level = energy.min(threshold) as u32 * 9 / threshold.max(1) as u32;
+        (0, (level * 16) as u8, (40 + level * 23) as u8)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncOutcome {
+    Synchronized(usize),
+    /// The state after step `start` repeats every `period` steps without
+    /// ever synchronising.
+    Cycle {
+        start: usize,
+        period: usize,
+    },
+    NotFoundWithin(usize),
 }
 
+impl std::fmt::Display for SyncOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SyncOutcome::Synchronized(step) => write!(f, "synchronized on step {}", step),
+            SyncOutcome::Cycle { start, period } => write!(
+                f,
+                "never synchronizes: cycle of period {} starting at step {}",
+                period, start
+            ),
+            SyncOutcome::NotFoundWithin(limit) => {
+                write!(f, "did not synchronize within {} steps", limit)
+            }
+        }
+    }
+}
+
+/// Why `solve_day11_part2` found no synchronised step.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncError {
+    Cycle { start: usize, period: usize },
+    NotFoundWithin(usize),
+}
+
+impl std::fmt::Display for SyncError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            SyncError::Cycle { start, period } => SyncOutcome::Cycle { start, period }.fmt(f),
+            SyncError::NotFoundWithin(limit) => SyncOutcome::NotFoundWithin(limit).fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for SyncError {}
+
+const SYNC_STEP_LIMIT: usize = 100_000;
+
+impl From<Vec<Vec<u8>>> for Grid {
+    fn from(energy: Vec<Vec<u8>>) -> Self {
+        let height = energy.len();
+        let width = energy.first().map_or(0, |row| row.len());
+        Grid {
+            energy,
+            width,
+            height,
+            flashes: 0,
+            rules: FlashRules::default(),
+        }
+    }
+}
+
+const BAND_ROWS: usize = 16;
+
 #[aoc_generator(day11)]
-pub fn input_generator_day11(input: &str) -> Grid {
-    let mut grid = [[0; 10]; 10];
-    input.split('\n').enumerate().for_each(|(i, s)| {
-        let row_vec = s
+pub fn input_generator_day11(input: &str) -> Result<Grid, ParseError> {
+    let mut energy: Vec<Vec<u8>> = vec![];
+    for (line, s) in input.trim_end().lines().enumerate() {
+        let row = s
             .chars()
-            .map(|ch| ch.to_digit(10).unwrap() as u8)
-            .collect_vec();
-        grid[i].copy_from_slice(&row_vec);
-    });
-    Grid {
-        energy: grid,
-        flashes: 0,
+            .enumerate()
+            .map(|(column, ch)| {
+                ch.to_digit(10)
+                    .map(|d| d as u8)
+                    .ok_or(ParseError::UnexpectedChar { line, column, ch })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if let Some(first) = energy.first() {
+            if row.len() != first.len() {
+                return Err(ParseError::RaggedRow {
+                    line,
+                    expected: first.len(),
+                    found: row.len(),
+                });
+            }
+        }
+        energy.push(row);
     }
+
+    if energy.first().is_none_or(|row| row.is_empty()) {
+        return Err(ParseError::Empty);
+    }
+
+    Ok(Grid::from(energy))
 }
 
 #[aoc(day11, part1)]
@@ -104,24 +652,28 @@ pub fn solve_day11_part1(input: &Grid) -> usize {
     input.flashes()
 }
 
-#[aoc(day11, part2)]
-pub fn solve_day11_part2(input: &Grid) -> usize {
+#[aoc(day11, part1, Parallel)]
+pub fn solve_day11_part1_parallel(input: &Grid) -> usize {
     let mut input = input.clone();
-    let mut counter = 0;
-    loop {
-        input.simulate();
-        counter += 1;
+    for _ in 0..100 {
+        input.simulate_parallel(BAND_ROWS);
+    }
+    input.flashes()
+}
 
-        if input.all_flashed() {
-            break;
-        }
+#[aoc(day11, part2)]
+pub fn solve_day11_part2(input: &Grid) -> Result<usize, SyncError> {
+    match input.find_sync(SYNC_STEP_LIMIT) {
+        SyncOutcome::Synchronized(step) => Ok(step),
+        SyncOutcome::Cycle { start, period } => Err(SyncError::Cycle { start, period }),
+        SyncOutcome::NotFoundWithin(limit) => Err(SyncError::NotFoundWithin(limit)),
     }
-    counter
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::helper::SplitMix64;
     use pretty_assertions::assert_eq;
 
     const DATA: &str = "5483143223
@@ -137,15 +689,312 @@ mod tests {
 
     #[test]
     fn test_day11_part1() {
-        let data = input_generator_day11(&DATA);
+        let data = input_generator_day11(DATA).unwrap();
         let result = solve_day11_part1(&data);
         assert_eq!(result, 1656);
     }
 
     #[test]
     fn test_day11_part2() {
-        let data = input_generator_day11(&DATA);
+        let data = input_generator_day11(DATA).unwrap();
         let result = solve_day11_part2(&data);
-        assert_eq!(result, 195);
+        assert_eq!(result, Ok(195));
+    }
+
+    #[test]
+    fn test_non_square_grid() {
+        let mut grid = input_generator_day11("11111\n19991\n19191").unwrap();
+        assert_eq!((grid.width(), grid.height()), (5, 3));
+        grid.simulate();
+        assert_eq!(grid.flashes(), 5);
+        assert_eq!(grid.get(2, 2), 7);
+        assert_eq!(grid.get(2, 1), 0);
+        assert_eq!(grid.get(0, 0), 3);
+    }
+
+    #[test]
+    fn test_all_flashed_small_grid() {
+        let mut grid = input_generator_day11("99\n99").unwrap();
+        grid.simulate();
+        assert!(grid.all_flashed());
+        assert_eq!(grid.flashes(), 4);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            input_generator_day11("123\n12").unwrap_err(),
+            ParseError::RaggedRow {
+                line: 1,
+                expected: 3,
+                found: 2
+            }
+        );
+        assert_eq!(
+            input_generator_day11("1x3").unwrap_err(),
+            ParseError::UnexpectedChar {
+                line: 0,
+                column: 1,
+                ch: 'x'
+            }
+        );
+        assert_eq!(input_generator_day11("").unwrap_err(), ParseError::Empty);
+        assert_eq!(
+            input_generator_day11("1x3").unwrap_err().to_string(),
+            "unexpected character 'x' at line 1, column 2"
+        );
+        assert_eq!(
+            input_generator_day11("123\n12").unwrap_err().to_string(),
+            "line 2 has 2 cells, expected 3"
+        );
+    }
+
+    #[test]
+    fn test_find_sync() {
+        let data = input_generator_day11(DATA).unwrap();
+        assert_eq!(data.find_sync(1000), SyncOutcome::Synchronized(195));
+        assert_eq!(data.find_sync(100), SyncOutcome::NotFoundWithin(100));
+        assert_eq!(data.find_sync(0), SyncOutcome::NotFoundWithin(0));
+    }
+
+    #[test]
+    fn test_find_sync_cycle() {
+        let data = input_generator_day11("05").unwrap();
+        assert_eq!(
+            data.find_sync(1000),
+            SyncOutcome::Cycle {
+                start: 0,
+                period: 9
+            }
+        );
+        assert_eq!(
+            solve_day11_part2(&data),
+            Err(SyncError::Cycle {
+                start: 0,
+                period: 9
+            })
+        );
+    }
+
+    #[test]
+    fn test_find_sync_hash_collisions() {
+        // every state hashes the same, so each step is checked against all earlier ones
+        let data = input_generator_day11(DATA).unwrap();
+        assert_eq!(
+            data.find_sync_with(1000, |_| 0),
+            SyncOutcome::Synchronized(195)
+        );
+        let data = input_generator_day11("05").unwrap();
+        assert_eq!(
+            data.find_sync_with(1000, |_| 0),
+            SyncOutcome::Cycle {
+                start: 0,
+                period: 9
+            }
+        );
+    }
+
+    #[test]
+    fn test_simulate_flash_events() {
+        let mut grid = input_generator_day11("11111\n19991\n19191").unwrap();
+        let flashed = grid.simulate();
+        let cells = flashed
+            .iter()
+            .map(|f| (f.x, f.y, f.depth))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            cells,
+            vec![(1, 1, 0), (2, 1, 0), (3, 1, 0), (1, 2, 0), (3, 2, 0)]
+        );
+
+        let mut grid = input_generator_day11("11111\n19991\n19891").unwrap();
+        let flashed = grid.simulate();
+        assert_eq!(flashed.len(), 6);
+        assert_eq!(
+            flashed.last(),
+            Some(&Flash {
+                x: 2,
+                y: 2,
+                depth: 1
+            })
+        );
+        assert_eq!(grid.flashes(), 6);
+    }
+
+    #[test]
+    fn test_write_ascii_frames() {
+        let grid = input_generator_day11("89\n11").unwrap();
+        let mut out = vec![];
+        grid.write_ascii_frames(2, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "step 0\n89\n11\n\nstep 1: 2 flashes, cascade depth 1\n00\n44\n\nstep 2: 0 flashes\n11\n55\n"
+        );
+    }
+
+    #[test]
+    fn test_write_ppm_frames() {
+        let grid = input_generator_day11("89\n11").unwrap();
+        let mut frames = vec![vec![]; 3];
+        let mut slots = frames.iter_mut();
+        grid.write_ppm_frames(2, 2, |_| Ok(slots.next().unwrap()))
+            .unwrap();
+        for frame in &frames {
+            assert!(frame.starts_with(b"P6\n4 4\n255\n"));
+            assert_eq!(frame.len(), 11 + 4 * 4 * 3);
+        }
+        // step 1: the top row flashed
+        assert_eq!(&frames[1][11..14], &[255, 255, 255]);
+    }
+
+    #[test]
+    fn test_default_rules_match_puzzle() {
+        let data = input_generator_day11(DATA).unwrap();
+        assert_eq!(data.rules(), FlashRules::default());
+        let data = data.with_rules(FlashRules::default());
+        assert_eq!(solve_day11_part1(&data), 1656);
+    }
+
+    #[test]
+    fn test_four_neighbourhood() {
+        let rules = FlashRules {
+            neighbourhood: Neighbourhood::Four,
+            ..FlashRules::default()
+        };
+        let mut grid = input_generator_day11("777\n797\n777")
+            .unwrap()
+            .with_rules(rules);
+        grid.simulate();
+        assert_eq!(grid.render_ascii(), "898\n909\n898\n");
+    }
+
+    #[test]
+    fn test_wrapping() {
+        let rules = FlashRules {
+            wrap: true,
+            ..FlashRules::default()
+        };
+        let mut grid = input_generator_day11("9000\n0000\n0000")
+            .unwrap()
+            .with_rules(rules);
+        let flashed = grid.simulate();
+        assert_eq!(flashed.len(), 1);
+        assert_eq!(grid.render_ascii(), "0212\n2212\n2212\n");
+    }
+
+    #[test]
+    fn test_threshold_and_gains() {
+        let rules = FlashRules {
+            threshold: 20,
+            step_gain: 5,
+            flash_gain: 3,
+            ..FlashRules::default()
+        };
+        let mut grid = input_generator_day11("91\n11").unwrap().with_rules(rules);
+        assert!(grid.simulate().is_empty());
+        assert!(grid.simulate().is_empty());
+        assert_eq!(grid.render_ascii(), "jb\nbb\n");
+        assert_eq!(grid.simulate().len(), 1);
+        assert_eq!(grid.render_ascii(), "0j\njj\n");
+    }
+
+    fn random_grid(width: usize, height: usize, seed: u64) -> Grid {
+        let mut rng = SplitMix64::new(seed);
+        Grid::from(
+            (0..height)
+                .map(|_| (0..width).map(|_| rng.below(10) as u8).collect())
+                .collect::<Vec<Vec<u8>>>(),
+        )
+    }
+
+    fn assert_parallel_matches(grid: Grid, steps: usize, band_rows: usize) {
+        let mut sequential = grid.clone();
+        let mut parallel = grid;
+        for step in 0..steps {
+            let expected = sequential.simulate().len();
+            assert_eq!(
+                parallel.simulate_parallel(band_rows),
+                expected,
+                "step {}",
+                step
+            );
+            assert_eq!(parallel.energy, sequential.energy, "step {}", step);
+        }
+        assert_eq!(parallel.flashes(), sequential.flashes());
+    }
+
+    #[test]
+    fn test_simulate_parallel_empty_grid() {
+        for energy in [vec![], vec![vec![]], vec![vec![]; 3]] {
+            let mut grid = Grid::from(energy);
+            grid.simulate();
+            assert_eq!(grid.simulate_parallel(4), 0);
+            assert_eq!(grid.flashes(), 0);
+        }
+    }
+
+    #[test]
+    fn test_day11_part1_parallel() {
+        let data = input_generator_day11(DATA).unwrap();
+        assert_eq!(solve_day11_part1_parallel(&data), 1656);
+    }
+
+    #[test]
+    fn test_simulate_parallel_matches_sequential() {
+        assert_parallel_matches(random_grid(80, 70, 1), 40, 7);
+        assert_parallel_matches(random_grid(64, 3, 2), 60, 1);
+
+        let rules = FlashRules {
+            neighbourhood: Neighbourhood::Four,
+            wrap: true,
+            threshold: 12,
+            step_gain: 2,
+            flash_gain: 3,
+        };
+        assert_parallel_matches(random_grid(70, 50, 3).with_rules(rules), 40, 5);
+        assert_parallel_matches(random_grid(2, 1, 4).with_rules(rules), 20, 1);
+
+        let rules = FlashRules {
+            wrap: true,
+            ..FlashRules::default()
+        };
+        assert_parallel_matches(random_grid(65, 20, 5).with_rules(rules), 40, 3);
+        assert_parallel_matches(random_grid(128, 9, 6).with_rules(rules), 40, 4);
+    }
+
+    /// Throughput of both engines on a 1000×1000 grid; run with
+    /// `cargo test --release -- --ignored --nocapture bench_simulate_parallel`.
+    /// The parallel engine only pulls ahead with several cores.
+    #[test]
+    #[ignore]
+    fn bench_simulate_parallel() {
+        const STEPS: usize = 20;
+        let grid = random_grid(1000, 1000, 42);
+        let cells = (grid.width() * grid.height() * STEPS) as f64;
+
+        let mut sequential = grid.clone();
+        let start = std::time::Instant::now();
+        for _ in 0..STEPS {
+            sequential.simulate();
+        }
+        let sequential_time = start.elapsed();
+
+        let mut parallel = grid;
+        let start = std::time::Instant::now();
+        for _ in 0..STEPS {
+            parallel.simulate_parallel(BAND_ROWS);
+        }
+        let parallel_time = start.elapsed();
+
+        println!(
+            "sequential: {:?} ({:.1} Mcells/s), parallel on {} threads: {:?} ({:.1} Mcells/s)",
+            sequential_time,
+            cells / sequential_time.as_secs_f64() / 1e6,
+            rayon::current_num_threads(),
+            parallel_time,
+            cells / parallel_time.as_secs_f64() / 1e6
+        );
+        assert_eq!(parallel.energy, sequential.energy);
+        assert_eq!(parallel.flashes(), sequential.flashes());
     }
 }
@@ -1,20 +1,66 @@
 use aoc_runner_derive::{aoc, aoc_generator};
+use num_bigint::BigUint;
+use std::io::{self, Write};
 
 pub type PopulationCounter = [u64; 9];
 
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ParseError {
+    AgeOutOfRange { age: u64, offset: usize },
+    MissingAge { offset: usize },
+    UnexpectedByte { byte: u8, offset: usize },
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::AgeOutOfRange { age, offset } => {
+                write!(f, "age {} at offset {} is out of range 0..=8", age, offset)
+            }
+            ParseError::MissingAge { offset } => write!(f, "missing age at offset {}", offset),
+            ParseError::UnexpectedByte { byte, offset } => write!(
+                f,
+                "unexpected byte {:?} at offset {}",
+                *byte as char, offset
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
 #[aoc_generator(day6)]
-pub fn input_generator_day6(input: &str) -> PopulationCounter {
+pub fn input_generator_day6(input: &str) -> Result<PopulationCounter, ParseError> {
     let mut pop: PopulationCounter = [0; 9];
 
-    for (idx, age) in pop.iter_mut().enumerate() {
-        *age = input
-            .split(',')
-            .map(|s| s.parse().unwrap())
-            .filter(|a: &usize| *a == idx)
-            .count() as u64;
+    let bytes = input.trim_end().as_bytes();
+    let mut age: Option<u64> = None;
+    let mut start = 0;
+
+    for (offset, &byte) in bytes.iter().chain(std::iter::once(&b',')).enumerate() {
+        match byte {
+            b'0'..=b'9' => {
+                if age.is_none() {
+                    start = offset;
+                }
+                let digit = (byte - b'0') as u64;
+                age = Some(age.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+            }
+            b',' => match age.take() {
+                Some(a) if a < 9 => pop[a as usize] += 1,
+                Some(a) => {
+                    return Err(ParseError::AgeOutOfRange {
+                        age: a,
+                        offset: start,
+                    })
+                }
+                None => return Err(ParseError::MissingAge { offset }),
+            },
+            _ => return Err(ParseError::UnexpectedByte { byte, offset }),
+        }
     }
 
-    pop
+    Ok(pop)
 }
 
 fn handle_day(pop: &mut PopulationCounter) {
@@ -27,6 +73,256 @@ fn handle_day(pop: &mut PopulationCounter) {
     pop[8] = birthing; // only birthed have counters of 8
 }
 
+fn checked_handle_day(pop: &PopulationCounter) -> Option<PopulationCounter> {
+    // after the shift, bucket 6 holds the former bucket 7 plus the births
+    pop[7].checked_add(pop[0])?;
+    let mut next = *pop;
+    handle_day(&mut next);
+    Some(next)
+}
+
+/// Iterator over the population histogram, starting with the initial state on day 0.
+///
+/// Ends early if the next day would overflow a `u64` bucket.
+#[derive(Clone, Debug)]
+pub struct PopulationSeries {
+    pop: Option<PopulationCounter>,
+}
+
+impl Iterator for PopulationSeries {
+    type Item = PopulationCounter;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let current = self.pop?;
+        self.pop = checked_handle_day(&current);
+        Some(current)
+    }
+}
+
+pub fn population_series(pop: &PopulationCounter) -> PopulationSeries {
+    PopulationSeries { pop: Some(*pop) }
+}
+
+pub fn total_population(pop: &PopulationCounter) -> u128 {
+    pop.iter().map(|&n| n as u128).sum()
+}
+
+/// First day on which the total population is strictly greater than `threshold`, or `None`
+/// if it never will be (an empty population).
+///
+/// Fails with `ForecastError::Overflow` if a `u64` bucket overflows first, which limits the
+/// reachable totals to about `9 * u64::MAX`.
+pub fn first_day_exceeding(
+    pop: &PopulationCounter,
+    threshold: u128,
+) -> Result<Option<usize>, ForecastError> {
+    if total_population(pop) == 0 {
+        return Ok(None);
+    }
+    population_series(pop)
+        .position(|p| total_population(&p) > threshold)
+        .map(Some)
+        .ok_or(ForecastError::Overflow)
+}
+
+/// Writes the age histogram for days `0..=days` as CSV, one row per day.
+///
+/// Fails with `InvalidData` if a `u64` bucket overflows before `days`.
+pub fn write_population_csv<W: Write>(
+    pop: &PopulationCounter,
+    days: usize,
+    mut writer: W,
+) -> io::Result<()> {
+    writeln!(
+        writer,
+        "day,age0,age1,age2,age3,age4,age5,age6,age7,age8,total"
+    )?;
+    let mut rows = 0;
+    for (day, p) in population_series(pop).take(days + 1).enumerate() {
+        write!(writer, "{}", day)?;
+        for count in p.iter() {
+            write!(writer, ",{}", count)?;
+        }
+        writeln!(writer, ",{}", total_population(&p))?;
+        rows += 1;
+    }
+    if rows <= days {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("population overflows a u64 bucket on day {}", rows),
+        ));
+    }
+    Ok(())
+}
+
+pub type TransitionMatrix<T> = [[T; 9]; 9];
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ForecastError {
+    Overflow,
+}
+
+/// Number representation used by the matrix forecaster.
+pub trait Arithmetic {
+    type Value: Clone;
+
+    fn value(&self, n: u64) -> Self::Value;
+    fn add(&self, a: &Self::Value, b: &Self::Value) -> Option<Self::Value>;
+    fn mul(&self, a: &Self::Value, b: &Self::Value) -> Option<Self::Value>;
+}
+
+/// `u128` arithmetic which reports overflow instead of wrapping.
+#[derive(Copy, Clone, Debug)]
+pub struct Checked;
+
+impl Arithmetic for Checked {
+    type Value = u128;
+
+    fn value(&self, n: u64) -> u128 {
+        n as u128
+    }
+
+    fn add(&self, a: &u128, b: &u128) -> Option<u128> {
+        a.checked_add(*b)
+    }
+
+    fn mul(&self, a: &u128, b: &u128) -> Option<u128> {
+        a.checked_mul(*b)
+    }
+}
+
+/// Arbitrary-precision arithmetic; never overflows.
+#[derive(Copy, Clone, Debug)]
+pub struct Big;
+
+impl Arithmetic for Big {
+    type Value = BigUint;
+
+    fn value(&self, n: u64) -> BigUint {
+        BigUint::from(n)
+    }
+
+    fn add(&self, a: &BigUint, b: &BigUint) -> Option<BigUint> {
+        Some(a + b)
+    }
+
+    fn mul(&self, a: &BigUint, b: &BigUint) -> Option<BigUint> {
+        Some(a * b)
+    }
+}
+
+/// Arithmetic modulo a non-zero modulus.
+#[derive(Copy, Clone, Debug)]
+pub struct Modular(u64);
+
+impl Modular {
+    /// `None` if `modulus` is zero.
+    pub fn new(modulus: u64) -> Option<Self> {
+        if modulus == 0 {
+            None
+        } else {
+            Some(Modular(modulus))
+        }
+    }
+
+    pub fn modulus(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Arithmetic for Modular {
+    type Value = u64;
+
+    fn value(&self, n: u64) -> u64 {
+        n % self.0
+    }
+
+    fn add(&self, a: &u64, b: &u64) -> Option<u64> {
+        Some(((*a as u128 + *b as u128) % self.0 as u128) as u64)
+    }
+
+    fn mul(&self, a: &u64, b: &u64) -> Option<u64> {
+        Some(((*a as u128 * *b as u128) % self.0 as u128) as u64)
+    }
+}
+
+/// The matrix `M` such that `M * pop` is the population after one `handle_day`.
+pub fn transition_matrix<A: Arithmetic>(arith: &A) -> TransitionMatrix<A::Value> {
+    let mut m: TransitionMatrix<A::Value> = array_init(|_, _| arith.value(0));
+    for i in 1..9 {
+        m[i - 1][i] = arith.value(1);
+    }
+    m[6][0] = arith.value(1);
+    m[8][0] = arith.value(1);
+    m
+}
+
+fn array_init<T, F>(f: F) -> [[T; 9]; 9]
+where
+    F: Fn(usize, usize) -> T,
+{
+    let rows: [usize; 9] = [0, 1, 2, 3, 4, 5, 6, 7, 8];
+    rows.map(|r| rows.map(|c| f(r, c)))
+}
+
+fn mat_mul<A: Arithmetic>(
+    arith: &A,
+    a: &TransitionMatrix<A::Value>,
+    b: &TransitionMatrix<A::Value>,
+) -> Result<TransitionMatrix<A::Value>, ForecastError> {
+    let mut result = array_init(|_, _| arith.value(0));
+    for (r, row) in result.iter_mut().enumerate() {
+        for (c, cell) in row.iter_mut().enumerate() {
+            for k in 0..9 {
+                let prod = arith
+                    .mul(&a[r][k], &b[k][c])
+                    .ok_or(ForecastError::Overflow)?;
+                *cell = arith.add(cell, &prod).ok_or(ForecastError::Overflow)?;
+            }
+        }
+    }
+    Ok(result)
+}
+
+fn mat_pow<A: Arithmetic>(
+    arith: &A,
+    mut days: u64,
+) -> Result<TransitionMatrix<A::Value>, ForecastError> {
+    let mut result = array_init(|r, c| arith.value((r == c) as u64));
+    let mut base = transition_matrix(arith);
+    while days > 0 {
+        if days & 1 == 1 {
+            result = mat_mul(arith, &result, &base)?;
+        }
+        days >>= 1;
+        if days > 0 {
+            base = mat_mul(arith, &base, &base)?;
+        }
+    }
+    Ok(result)
+}
+
+/// Total population after `days` days, in O(log days) matrix products.
+///
+/// With `Checked`, overflow of any intermediate product is reported as an error.
+pub fn forecast<A: Arithmetic>(
+    pop: &PopulationCounter,
+    days: u64,
+    arith: &A,
+) -> Result<A::Value, ForecastError> {
+    let m = mat_pow(arith, days)?;
+    let mut total = arith.value(0);
+    for row in m.iter() {
+        for (entry, &count) in row.iter().zip(pop.iter()) {
+            let fish = arith
+                .mul(entry, &arith.value(count))
+                .ok_or(ForecastError::Overflow)?;
+            total = arith.add(&total, &fish).ok_or(ForecastError::Overflow)?;
+        }
+    }
+    Ok(total)
+}
+
 #[aoc(day6, part1)]
 pub fn solve_day6_part1(input: &PopulationCounter) -> u64 {
     let mut pop = *input;
@@ -58,21 +354,148 @@ mod tests {
 
     #[test]
     fn test_input_generator() {
-        let result = input_generator_day6(DATA);
+        let result = input_generator_day6(DATA).unwrap();
+        assert_eq!(result, [0, 1, 1, 2, 1, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_input_generator_whitespace() {
+        let result = input_generator_day6("3,4,3,1,2\n").unwrap();
         assert_eq!(result, [0, 1, 1, 2, 1, 0, 0, 0, 0]);
+        let result = input_generator_day6("8,0,0  \r\n\n").unwrap();
+        assert_eq!(result, [2, 0, 0, 0, 0, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn test_input_generator_errors() {
+        assert_eq!(
+            input_generator_day6("3,12,1"),
+            Err(ParseError::AgeOutOfRange { age: 12, offset: 2 })
+        );
+        assert_eq!(
+            input_generator_day6("3,,1"),
+            Err(ParseError::MissingAge { offset: 2 })
+        );
+        assert_eq!(
+            input_generator_day6("3, 1"),
+            Err(ParseError::UnexpectedByte {
+                byte: b' ',
+                offset: 2
+            })
+        );
+        assert_eq!(
+            input_generator_day6(""),
+            Err(ParseError::MissingAge { offset: 0 })
+        );
+    }
+
+    #[test]
+    fn test_input_generator_large() {
+        let input = vec!["6"; 1_000_000].join(",");
+        let result = input_generator_day6(&input).unwrap();
+        assert_eq!(result[6], 1_000_000);
     }
 
     #[test]
     fn test_day6_part1() {
-        let data = input_generator_day6(DATA);
+        let data = input_generator_day6(DATA).unwrap();
         let result = solve_day6_part1(&data);
         assert_eq!(result, 5934);
     }
 
     #[test]
     fn test_day6_part2() {
-        let data = input_generator_day6(DATA);
+        let data = input_generator_day6(DATA).unwrap();
         let result = solve_day6_part2(&data);
         assert_eq!(result, 26984457539);
     }
+
+    #[test]
+    fn test_forecast_matches_simulation() {
+        let data = input_generator_day6(DATA).unwrap();
+        assert_eq!(forecast(&data, 80, &Checked), Ok(5934));
+        assert_eq!(forecast(&data, 256, &Checked), Ok(26984457539));
+        assert_eq!(
+            forecast(&data, 256, &Big),
+            Ok(BigUint::from(26984457539u64))
+        );
+        assert_eq!(
+            forecast(&data, 256, &Modular::new(1_000_000_007).unwrap()),
+            Ok(984457357)
+        );
+    }
+
+    #[test]
+    fn test_population_series() {
+        let data = input_generator_day6(DATA).unwrap();
+        let series = population_series(&data).take(19).collect::<Vec<_>>();
+        assert_eq!(series[0], data);
+        assert_eq!(series[1], [1, 1, 2, 1, 0, 0, 0, 0, 0]);
+        assert_eq!(total_population(&series[18]), 26);
+        assert_eq!(
+            population_series(&data)
+                .nth(80)
+                .map(|p| total_population(&p)),
+            Some(5934)
+        );
+    }
+
+    #[test]
+    fn test_first_day_exceeding() {
+        let data = input_generator_day6(DATA).unwrap();
+        assert_eq!(first_day_exceeding(&data, 5), Ok(Some(2)));
+        assert_eq!(first_day_exceeding(&data, 5933), Ok(Some(80)));
+        assert_eq!(first_day_exceeding(&[0; 9], 1), Ok(None));
+        assert_eq!(
+            first_day_exceeding(&data, u128::MAX),
+            Err(ForecastError::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_population_series_stops_before_overflow() {
+        let pop = [1, 0, 0, 0, 0, 0, 0, u64::MAX, 0];
+        assert_eq!(population_series(&pop).count(), 1);
+        assert_eq!(
+            first_day_exceeding(&pop, u64::MAX as u128 + 1),
+            Err(ForecastError::Overflow)
+        );
+
+        let pop = [1, 0, 0, 0, 0, 0, 0, u64::MAX - 1, 0];
+        let series = population_series(&pop).take(2).collect::<Vec<_>>();
+        assert_eq!(series[1], [0, 0, 0, 0, 0, 0, u64::MAX, 0, 1]);
+    }
+
+    #[test]
+    fn test_write_population_csv() {
+        let data = input_generator_day6(DATA).unwrap();
+        let mut out = vec![];
+        write_population_csv(&data, 2, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "day,age0,age1,age2,age3,age4,age5,age6,age7,age8,total
+0,0,1,1,2,1,0,0,0,0,5
+1,1,1,2,1,0,0,0,0,0,5
+2,1,2,1,0,0,0,1,0,1,6
+"
+        );
+
+        let mut out = vec![];
+        let err = write_population_csv(&data, 1000, &mut out).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let rows = String::from_utf8(out).unwrap().lines().count() - 1;
+        assert_eq!(
+            err.to_string(),
+            format!("population overflows a u64 bucket on day {}", rows)
+        );
+    }
+
+    #[test]
+    fn test_forecast_overflow() {
+        let data = input_generator_day6(DATA).unwrap();
+        assert!(forecast(&data, 1_000, &Checked).is_err());
+        assert!(forecast(&data, 1_000_000_000, &Modular::new(1_000_000_007).unwrap()).is_ok());
+        assert!(Modular::new(0).is_none());
+        assert_eq!(forecast(&data, 10, &Modular::new(1).unwrap()), Ok(0));
+    }
 }
@@ -1,10 +1,344 @@
 use aoc_runner_derive::{aoc, aoc_generator};
+use std::collections::BTreeMap;
 
 #[aoc_generator(day7)]
 pub fn input_generator_day7(input: &str) -> Vec<u32> {
     input.split(',').map(|s| s.parse().unwrap()).collect()
 }
 
+pub trait FuelCost {
+    fn cost(&self, distance: u64) -> u64;
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Linear;
+
+impl FuelCost for Linear {
+    fn cost(&self, distance: u64) -> u64 {
+        distance
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Triangular;
+
+impl FuelCost for Triangular {
+    fn cost(&self, distance: u64) -> u64 {
+        distance * (distance + 1) / 2
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Quadratic;
+
+impl FuelCost for Quadratic {
+    fn cost(&self, distance: u64) -> u64 {
+        distance * distance
+    }
+}
+
+impl<F> FuelCost for F
+where
+    F: Fn(u64) -> u64,
+{
+    fn cost(&self, distance: u64) -> u64 {
+        self(distance)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Alignment {
+    pub position: u32,
+    pub cost: u64,
+}
+
+fn distance(a: u32, b: u32) -> u64 {
+    (u32::max(a, b) - u32::min(a, b)) as u64
+}
+
+pub fn total_cost<C: FuelCost + ?Sized>(crabs: &[u32], position: u32, cost: &C) -> u64 {
+    crabs
+        .iter()
+        .map(|&n| cost.cost(distance(n, position)))
+        .sum()
+}
+
+/// Finds the cheapest position between the outermost crabs for any cost function.
+///
+/// Ties are broken towards the lowest position. Returns `None` if there are no crabs.
+pub fn align<C: FuelCost + ?Sized>(crabs: &[u32], cost: &C) -> Option<Alignment> {
+    let min = *crabs.iter().min()?;
+    let max = *crabs.iter().max()?;
+    (min..=max)
+        .map(|position| Alignment {
+            position,
+            cost: total_cost(crabs, position, cost),
+        })
+        .min_by_key(|a| a.cost)
+}
+
+/// Exact optimum for triangular cost in O(n).
+///
+/// The cost is convex and its minimum lies within 1/2 of the mean, so only the positions
+/// around `floor(mean)` need to be checked.
+pub fn align_triangular(crabs: &[u32]) -> Option<Alignment> {
+    if crabs.is_empty() {
+        return None;
+    }
+    let sum: u64 = crabs.iter().map(|&n| n as u64).sum();
+    let mean = (sum / crabs.len() as u64) as u32;
+    let lo = mean.saturating_sub(1);
+    let hi = mean.saturating_add(1);
+    (lo..=hi)
+        .map(|position| Alignment {
+            position,
+            cost: total_cost(crabs, position, &Triangular),
+        })
+        .min_by_key(|a| a.cost)
+}
+
+/// Ternary search for any cost function that is convex in position, in O(n log range).
+pub fn align_convex<C: FuelCost + ?Sized>(crabs: &[u32], cost: &C) -> Option<Alignment> {
+    let mut lo = *crabs.iter().min()?;
+    let mut hi = *crabs.iter().max()?;
+    while hi - lo > 2 {
+        let m1 = lo + (hi - lo) / 3;
+        let m2 = hi - (hi - lo) / 3;
+        if total_cost(crabs, m1, cost) <= total_cost(crabs, m2, cost) {
+            hi = m2 - 1;
+        } else {
+            lo = m1 + 1;
+        }
+    }
+    (lo..=hi)
+        .map(|position| Alignment {
+            position,
+            cost: total_cost(crabs, position, cost),
+        })
+        .min_by_key(|a| a.cost)
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct WeightedCrab<const D: usize> {
+    pub position: [i64; D],
+    pub weight: u64,
+}
+
+fn parse_weighted<const D: usize>(s: &str, separator: char) -> WeightedCrab<D> {
+    let (position_str, weight) = match s.split_once(':') {
+        Some((p, w)) => (p, w.trim().parse().unwrap()),
+        None => (s, 1),
+    };
+    let mut position = [0; D];
+    let mut parts = position_str.split(separator);
+    for p in position.iter_mut() {
+        *p = parts.next().unwrap().trim().parse().unwrap();
+    }
+    assert!(parts.next().is_none(), "Too many coordinates in '{}'", s);
+    WeightedCrab { position, weight }
+}
+
+/// Parses day 7 style input where each entry may carry a weight, e.g. `16:3,1,2:2`.
+pub fn input_generator_day7_weighted(input: &str) -> Vec<WeightedCrab<1>> {
+    input
+        .trim()
+        .split(',')
+        .map(|s| parse_weighted(s, ','))
+        .collect()
+}
+
+/// Parses one crab per line as comma-separated coordinates with an optional weight,
+/// e.g. `3,-1,4:2`.
+pub fn input_generator_crabs<const D: usize>(input: &str) -> Vec<WeightedCrab<D>> {
+    input
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| parse_weighted(l, ','))
+        .collect()
+}
+
+/// Collapses plain positions into one weighted crab per distinct position.
+pub fn group_crabs(crabs: &[u32]) -> Vec<WeightedCrab<1>> {
+    let mut counts = BTreeMap::new();
+    for &c in crabs {
+        *counts.entry(c).or_insert(0) += 1;
+    }
+    counts
+        .into_iter()
+        .map(|(position, weight)| WeightedCrab {
+            position: [position as i64],
+            weight,
+        })
+        .collect()
+}
+
+fn weighted_median(values: &mut [(i64, u64)]) -> Option<i64> {
+    // u128 sums cannot overflow for any slice of u64 weights
+    let total: u128 = values.iter().map(|v| v.1 as u128).sum();
+    if total == 0 {
+        return None;
+    }
+    values.sort_unstable_by_key(|v| v.0);
+    let mut cumulative: u128 = 0;
+    for &(value, weight) in values.iter() {
+        cumulative += weight as u128;
+        if cumulative * 2 >= total {
+            return Some(value);
+        }
+    }
+    unreachable!()
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct WeightedAlignment<const D: usize> {
+    pub position: [i64; D],
+    pub cost: u128,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum AlignError {
+    /// There are no crabs, or they all weigh nothing.
+    NoWeight,
+    /// The total cost does not fit in a `u128`.
+    Overflow,
+}
+
+impl std::fmt::Display for AlignError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AlignError::NoWeight => write!(f, "no crabs with a non-zero weight"),
+            AlignError::Overflow => write!(f, "alignment cost overflows a u128"),
+        }
+    }
+}
+
+impl std::error::Error for AlignError {}
+
+/// Total weighted Manhattan distance to `position`, or `None` if it overflows a `u128`.
+pub fn l1_cost<const D: usize>(crabs: &[WeightedCrab<D>], position: [i64; D]) -> Option<u128> {
+    crabs.iter().try_fold(0u128, |total, c| {
+        let distance: u128 = c
+            .position
+            .iter()
+            .zip(position.iter())
+            .map(|(&a, &b)| (a as i128 - b as i128).unsigned_abs())
+            .sum();
+        total.checked_add(distance.checked_mul(c.weight as u128)?)
+    })
+}
+
+/// Exact optimum for linear (Manhattan) fuel cost, using the weighted median of each axis.
+pub fn align_l1<const D: usize>(
+    crabs: &[WeightedCrab<D>],
+) -> Result<WeightedAlignment<D>, AlignError> {
+    let mut position = [0; D];
+    for (axis, p) in position.iter_mut().enumerate() {
+        let mut values = crabs
+            .iter()
+            .map(|c| (c.position[axis], c.weight))
+            .collect::<Vec<_>>();
+        *p = weighted_median(&mut values).ok_or(AlignError::NoWeight)?;
+    }
+    Ok(WeightedAlignment {
+        position,
+        cost: l1_cost(crabs, position).ok_or(AlignError::Overflow)?,
+    })
+}
+
+fn euclidean_distance<const D: usize>(a: &[i64; D], b: &[f64; D]) -> f64 {
+    a.iter()
+        .zip(b.iter())
+        .map(|(&a, &b)| (a as f64 - b).powi(2))
+        .sum::<f64>()
+        .sqrt()
+}
+
+pub fn euclidean_cost<const D: usize>(crabs: &[WeightedCrab<D>], position: [f64; D]) -> f64 {
+    crabs
+        .iter()
+        .map(|c| euclidean_distance(&c.position, &position) * c.weight as f64)
+        .sum()
+}
+
+/// Approximates the weighted geometric median with Weiszfeld's algorithm, using the
+/// Vardi–Zhang modification when the estimate lands on a crab.
+///
+/// Starts at the weighted centroid and stops once an iteration moves less than
+/// `tolerance`, or after `max_iterations`.
+pub fn geometric_median<const D: usize>(
+    crabs: &[WeightedCrab<D>],
+    tolerance: f64,
+    max_iterations: usize,
+) -> Option<[f64; D]> {
+    let total: u128 = crabs.iter().map(|c| c.weight as u128).sum();
+    if total == 0 {
+        return None;
+    }
+
+    let mut estimate = [0.0; D];
+    for c in crabs {
+        for (e, &p) in estimate.iter_mut().zip(c.position.iter()) {
+            *e += p as f64 * c.weight as f64 / total as f64;
+        }
+    }
+
+    for _ in 0..max_iterations {
+        let mut numerator = [0.0; D];
+        let mut denominator = 0.0;
+        // weight of the crabs sitting on the estimate, and the pull of all the others
+        let mut coincident = 0.0;
+        let mut pull = [0.0; D];
+        for c in crabs.iter().filter(|c| c.weight > 0) {
+            let distance = euclidean_distance(&c.position, &estimate);
+            if distance < f64::EPSILON {
+                coincident += c.weight as f64;
+                continue;
+            }
+            let w = c.weight as f64 / distance;
+            for ((n, r), (&p, &e)) in numerator
+                .iter_mut()
+                .zip(pull.iter_mut())
+                .zip(c.position.iter().zip(estimate.iter()))
+            {
+                *n += p as f64 * w;
+                *r += (p as f64 - e) * w;
+            }
+            denominator += w;
+        }
+        if denominator == 0.0 {
+            break;
+        }
+        let pull = pull.iter().map(|r| r * r).sum::<f64>().sqrt();
+        if coincident > 0.0 && pull <= coincident {
+            // the crabs on the estimate outweigh everything else: it is the median
+            break;
+        }
+
+        // step towards the Weiszfeld point, held back by the crabs on the estimate
+        let ratio = if coincident > 0.0 {
+            coincident / pull
+        } else {
+            0.0
+        };
+        let mut next = [0.0; D];
+        for ((n, &num), &e) in next.iter_mut().zip(numerator.iter()).zip(estimate.iter()) {
+            *n = (1.0 - ratio) * num / denominator + ratio * e;
+        }
+        let moved = next
+            .iter()
+            .zip(estimate.iter())
+            .map(|(a, b)| (a - b).powi(2))
+            .sum::<f64>()
+            .sqrt();
+        estimate = next;
+        if moved < tolerance {
+            break;
+        }
+    }
+
+    Some(estimate)
+}
+
 #[aoc(day7, part1)]
 pub fn solve_day7_part1(input: &[u32]) -> u32 {
     let mut crabs = input.to_vec();
@@ -17,42 +351,207 @@ pub fn solve_day7_part1(input: &[u32]) -> u32 {
 }
 
 #[aoc(day7, part2)]
-pub fn solve_day7_part2(input: &[u32]) -> u32 {
-    // Average is close but not guaranteed (at most off by one for input ranges given...)
-    // but this is the slower, more general solution
-
-    let get_cost = |pos: u32| -> u32 {
-        input
-            .iter()
-            .map(|&n| u32::max(n, pos) - u32::min(n, pos))
-            .map(|n| n * (n + 1) / 2)
-            .sum()
-    };
-
-    let pos = (0..*input.iter().max().unwrap())
-        .min_by(|&a, &b| get_cost(a).cmp(&get_cost(b)))
-        .unwrap();
-    get_cost(pos)
+pub fn solve_day7_part2(input: &[u32]) -> u64 {
+    align_triangular(input).unwrap().cost
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::helper::SplitMix64;
     use pretty_assertions::assert_eq;
 
     const DATA: &str = "16,1,2,0,4,2,7,1,2,14";
 
     #[test]
     fn test_day7_part1() {
-        let data = input_generator_day7(&DATA);
+        let data = input_generator_day7(DATA);
         let result = solve_day7_part1(&data);
         assert_eq!(result, 37);
     }
 
     #[test]
     fn test_day7_part2() {
-        let data = input_generator_day7(&DATA);
+        let data = input_generator_day7(DATA);
         let result = solve_day7_part2(&data);
         assert_eq!(result, 168);
     }
+
+    #[test]
+    fn test_align() {
+        let data = input_generator_day7(DATA);
+        assert_eq!(
+            align(&data, &Linear),
+            Some(Alignment {
+                position: 2,
+                cost: 37
+            })
+        );
+        assert_eq!(
+            align(&data, &Triangular),
+            Some(Alignment {
+                position: 5,
+                cost: 168
+            })
+        );
+        assert_eq!(align(&data, &Quadratic).map(|a| a.position), Some(5));
+        assert_eq!(align(&[], &Linear), None);
+    }
+
+    #[test]
+    fn test_align_fast() {
+        let data = input_generator_day7(DATA);
+        let expected = align(&data, &Triangular);
+        assert_eq!(align_triangular(&data), expected);
+        assert_eq!(align_convex(&data, &Triangular), expected);
+        assert_eq!(align_convex(&data, &Linear), align(&data, &Linear));
+        assert_eq!(align_triangular(&[]), None);
+    }
+
+    #[test]
+    fn test_align_fast_matches_brute_force() {
+        // deterministic pseudo-random inputs, including skewed ones where the mean is far
+        // from the median
+        let mut rng = SplitMix64::new(12345);
+        for _ in 0..50 {
+            let crabs = (0..25)
+                .map(|i| {
+                    let n = rng.below(200) as u32;
+                    if i % 5 == 0 {
+                        n * 10
+                    } else {
+                        n
+                    }
+                })
+                .collect::<Vec<_>>();
+            let expected = align(&crabs, &Triangular).unwrap().cost;
+            assert_eq!(align_triangular(&crabs).unwrap().cost, expected);
+            assert_eq!(align_convex(&crabs, &Triangular).unwrap().cost, expected);
+        }
+    }
+
+    #[test]
+    fn test_align_large_distances() {
+        // n * (n + 1) / 2 overflows u32 for these distances
+        let crabs = [0, 100_000, 200_000];
+        let result = align_triangular(&crabs).unwrap();
+        assert_eq!(result.position, 100_000);
+        assert_eq!(result.cost, 2 * 5_000_050_000);
+    }
+
+    #[test]
+    fn test_weighted_input() {
+        let crabs = input_generator_day7_weighted("16:3,1,2:2");
+        assert_eq!(
+            crabs,
+            vec![
+                WeightedCrab {
+                    position: [16],
+                    weight: 3
+                },
+                WeightedCrab {
+                    position: [1],
+                    weight: 1
+                },
+                WeightedCrab {
+                    position: [2],
+                    weight: 2
+                },
+            ]
+        );
+        let crabs = input_generator_crabs::<2>("1,2\n-3,4:5\n");
+        assert_eq!(crabs[1].position, [-3, 4]);
+        assert_eq!(crabs[1].weight, 5);
+    }
+
+    #[test]
+    fn test_align_l1_weighted() {
+        let data = input_generator_day7(DATA);
+        let grouped = group_crabs(&data);
+        assert_eq!(grouped.len(), 7);
+        assert_eq!(
+            align_l1(&grouped),
+            Ok(WeightedAlignment {
+                position: [2],
+                cost: 37
+            })
+        );
+
+        let crabs = input_generator_day7_weighted("0:10,100:1");
+        assert_eq!(align_l1(&crabs).unwrap().position, [0]);
+        assert_eq!(align_l1::<1>(&[]), Err(AlignError::NoWeight));
+    }
+
+    #[test]
+    fn test_align_l1_huge_weights() {
+        let crabs = input_generator_day7_weighted("16:18446744073709551615,1");
+        assert_eq!(
+            align_l1(&crabs),
+            Ok(WeightedAlignment {
+                position: [16],
+                cost: 15
+            })
+        );
+        let crabs = input_generator_day7_weighted("1:18446744073709551615,0:18446744073709551615");
+        assert_eq!(align_l1(&crabs).unwrap().cost, u64::MAX as u128);
+
+        let far = format!(
+            "{},{}:{}\n{},{}:{}\n{},{}:{}",
+            i64::MIN,
+            i64::MIN,
+            u64::MAX,
+            i64::MAX,
+            i64::MAX,
+            u64::MAX,
+            i64::MAX,
+            i64::MAX,
+            u64::MAX
+        );
+        let crabs = input_generator_crabs::<2>(&far);
+        assert_eq!(align_l1(&crabs), Err(AlignError::Overflow));
+    }
+
+    #[test]
+    fn test_align_l1_multi_dimensional() {
+        let crabs = input_generator_crabs::<3>("0,0,0\n2,5,1\n4,1,9:2");
+        assert_eq!(
+            align_l1(&crabs),
+            Ok(WeightedAlignment {
+                position: [2, 1, 1],
+                cost: 4 + 4 + 2 * 10
+            })
+        );
+    }
+
+    #[test]
+    fn test_geometric_median() {
+        // symmetric square: the median is the centre
+        let crabs = input_generator_crabs::<2>("0,0\n2,0\n0,2\n2,2");
+        let median = geometric_median(&crabs, 1e-9, 1000).unwrap();
+        assert!((median[0] - 1.0).abs() < 1e-6);
+        assert!((median[1] - 1.0).abs() < 1e-6);
+
+        // a heavy crab pulls the median onto itself
+        let crabs = input_generator_crabs::<2>("0,0:10\n5,0\n0,5");
+        let median = geometric_median(&crabs, 1e-9, 1000).unwrap();
+        assert!(euclidean_cost(&crabs, median) <= euclidean_cost(&crabs, [0.0, 0.0]) + 1e-6);
+        assert!(median[0].abs() < 1e-3 && median[1].abs() < 1e-3);
+
+        // the centroid already sits on the heavy crab, which is the exact median
+        let crabs = input_generator_crabs::<1>("0:10\n2\n-1:2");
+        assert_eq!(geometric_median(&crabs, 1e-9, 1000), Some([0.0]));
+    }
+
+    #[test]
+    fn test_align_custom_cost() {
+        let data = input_generator_day7(DATA);
+        let double = |d: u64| 2 * d;
+        assert_eq!(
+            align(&data, &double),
+            Some(Alignment {
+                position: 2,
+                cost: 74
+            })
+        );
+    }
 }
@@ -1,10 +1,37 @@
+use crate::helper::SplitMix64;
 use aoc_runner_derive::{aoc, aoc_generator};
-use enumflags2::{bitflags, BitFlags};
+use enumflags2::{bitflags, BitFlag, BitFlags};
 use itertools::Itertools;
 
+/// A set of signal lines driving one display; wires are written as `a`, `b`, ... in order.
+pub trait SegmentLine: BitFlag + Copy + Eq + std::fmt::Debug {
+    fn wire(ch: char) -> Self {
+        let idx = (ch as u32).wrapping_sub('a' as u32) as usize;
+        BitFlags::<Self>::all()
+            .iter()
+            .nth(idx)
+            .unwrap_or_else(|| panic!("Unexpected character '{}'", ch))
+    }
+
+    fn segments(input: &str) -> BitFlags<Self> {
+        let mut result = BitFlags::default();
+        for ch in input.chars() {
+            result |= Self::wire(ch);
+        }
+        result
+    }
+
+    fn font(glyphs: &[(char, &str)]) -> Vec<(char, BitFlags<Self>)> {
+        glyphs
+            .iter()
+            .map(|&(symbol, s)| (symbol, Self::segments(s)))
+            .collect()
+    }
+}
+
 #[bitflags]
 #[repr(u8)]
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum SignalLine {
     A = 1 << 0,
     B = 1 << 1,
@@ -38,10 +65,69 @@ impl SignalLine {
     }
 }
 
+impl SegmentLine for SignalLine {
+    fn wire(ch: char) -> Self {
+        Self::from_char(ch)
+    }
+}
+
+// A top, B upper right, C lower right, D bottom, E lower left, F upper left,
+// G middle left, H middle right, I upper-left diagonal, J upper centre,
+// K upper-right diagonal, L lower-right diagonal, M lower centre, N lower-left diagonal
+#[bitflags]
+#[repr(u16)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum FourteenSegment {
+    A = 1 << 0,
+    B = 1 << 1,
+    C = 1 << 2,
+    D = 1 << 3,
+    E = 1 << 4,
+    F = 1 << 5,
+    G = 1 << 6,
+    H = 1 << 7,
+    I = 1 << 8,
+    J = 1 << 9,
+    K = 1 << 10,
+    L = 1 << 11,
+    M = 1 << 12,
+    N = 1 << 13,
+}
+
+impl SegmentLine for FourteenSegment {}
+
+// As FourteenSegment, but with the top and bottom bars split in halves:
+// A/B top left/right, C upper right, D lower right, E/F bottom right/left, G lower left,
+// H upper left, I middle left, J middle right, K upper-left diagonal, L upper centre,
+// M upper-right diagonal, N lower-right diagonal, O lower centre, P lower-left diagonal
+#[bitflags]
+#[repr(u16)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SixteenSegment {
+    A = 1 << 0,
+    B = 1 << 1,
+    C = 1 << 2,
+    D = 1 << 3,
+    E = 1 << 4,
+    F = 1 << 5,
+    G = 1 << 6,
+    H = 1 << 7,
+    I = 1 << 8,
+    J = 1 << 9,
+    K = 1 << 10,
+    L = 1 << 11,
+    M = 1 << 12,
+    N = 1 << 13,
+    O = 1 << 14,
+    P = 1 << 15,
+}
+
+impl SegmentLine for SixteenSegment {}
+
 #[derive(Clone, Debug)]
-pub struct Entry {
-    pub patterns: [BitFlags<SignalLine>; 10],
-    pub output: [BitFlags<SignalLine>; 4],
+pub struct Entry<T: SegmentLine = SignalLine> {
+    pub patterns: Vec<BitFlags<T>>,
+    pub output: Vec<BitFlags<T>>,
 }
 
 pub fn is_unique(signal: &BitFlags<SignalLine>) -> bool {
@@ -50,33 +136,24 @@ pub fn is_unique(signal: &BitFlags<SignalLine>) -> bool {
     matches!(count, 2 | 3 | 4 | 7)
 }
 
-#[aoc_generator(day8)]
-pub fn input_generator_day8(input: &str) -> Vec<Entry> {
+pub fn parse_entries<T: SegmentLine>(input: &str) -> Vec<Entry<T>> {
     input
-        .split('\n')
+        .lines()
         .map(|s| {
             let parts = s.split('|').collect_vec();
-            let patterns_vec = parts[0]
-                .trim()
-                .split(' ')
-                .map(|s| SignalLine::from_string(s))
-                .collect_vec();
-            let output_vec = parts[1]
-                .trim()
-                .split(' ')
-                .map(|s| SignalLine::from_string(s))
-                .collect_vec();
-
-            let mut patterns = [BitFlags::default(); 10];
-            let mut output = [BitFlags::default(); 4];
-            patterns.copy_from_slice(&patterns_vec);
-            output.copy_from_slice(&output_vec);
+            let patterns = parts[0].split_whitespace().map(T::segments).collect_vec();
+            let output = parts[1].split_whitespace().map(T::segments).collect_vec();
 
             Entry { patterns, output }
         })
         .collect()
 }
 
+#[aoc_generator(day8)]
+pub fn input_generator_day8(input: &str) -> Vec<Entry> {
+    parse_entries(input)
+}
+
 #[aoc(day8, part1)]
 pub fn solve_day8_part1(input: &[Entry]) -> usize {
     input
@@ -85,131 +162,601 @@ pub fn solve_day8_part1(input: &[Entry]) -> usize {
         .sum()
 }
 
-#[allow(clippy::many_single_char_names)]
-pub fn solve_map(entry: &Entry) -> [BitFlags<SignalLine>; 10] {
+pub const SEVEN_SEGMENT_DIGITS: [&str; 10] = [
+    "abcefg", "cf", "acdeg", "acdfg", "bcdf", "abdfg", "abdefg", "acf", "abcdefg", "abcdfg",
+];
+
+pub const FOURTEEN_SEGMENT_FONT: [(char, &str); 36] = [
+    ('0', "abcdefkn"),
+    ('1', "bc"),
+    ('2', "abdegh"),
+    ('3', "abcdh"),
+    ('4', "bcfgh"),
+    ('5', "adfgl"),
+    ('6', "acdefgh"),
+    ('7', "abc"),
+    ('8', "abcdefgh"),
+    ('9', "abcdfgh"),
+    ('A', "abcefgh"),
+    ('B', "abcdhjm"),
+    ('C', "adef"),
+    ('D', "abcdjm"),
+    ('E', "adefg"),
+    ('F', "aefg"),
+    ('G', "acdefh"),
+    ('H', "bcefgh"),
+    ('I', "adjm"),
+    ('J', "bcde"),
+    ('K', "efgkl"),
+    ('L', "def"),
+    ('M', "bcefik"),
+    ('N', "bcefil"),
+    ('O', "abcdef"),
+    ('P', "abefgh"),
+    ('Q', "abcdefl"),
+    ('R', "abefghl"),
+    ('S', "acdfgh"),
+    ('T', "ajm"),
+    ('U', "bcdef"),
+    ('V', "efkn"),
+    ('W', "bcefln"),
+    ('X', "ikln"),
+    ('Y', "ikm"),
+    ('Z', "adkn"),
+];
+
+pub const SIXTEEN_SEGMENT_FONT: [(char, &str); 42] = [
+    ('0', "abcdefghmp"),
+    ('1', "cd"),
+    ('2', "abcefgij"),
+    ('3', "abcdefj"),
+    ('4', "cdhij"),
+    ('5', "abefhin"),
+    ('6', "abdefghij"),
+    ('7', "abcd"),
+    ('8', "abcdefghij"),
+    ('9', "abcdefhij"),
+    ('A', "abcdghij"),
+    ('B', "abcdefjlo"),
+    ('C', "abefgh"),
+    ('D', "abcdeflo"),
+    ('E', "abefghi"),
+    ('F', "abghi"),
+    ('G', "abdefghj"),
+    ('H', "cdghij"),
+    ('I', "abeflo"),
+    ('J', "cdefg"),
+    ('K', "ghimn"),
+    ('L', "efgh"),
+    ('M', "cdghkm"),
+    ('N', "cdghkn"),
+    ('O', "abcdefgh"),
+    ('P', "abcghij"),
+    ('Q', "abcdefghn"),
+    ('R', "abcghijn"),
+    ('S', "abdefhij"),
+    ('T', "ablo"),
+    ('U', "cdefgh"),
+    ('V', "ghmp"),
+    ('W', "cdghnp"),
+    ('X', "kmnp"),
+    ('Y', "kmo"),
+    ('Z', "abefmp"),
+    ('c', "fgi"),
+    ('d', "cdejo"),
+    ('f', "bijlo"),
+    ('n', "gio"),
+    ('r', "gi"),
+    ('u', "fgo"),
+];
+
+pub fn seven_segment_alphabet() -> [BitFlags<SignalLine>; 10] {
     let mut result = [BitFlags::default(); 10];
+    for (r, s) in result.iter_mut().zip(SEVEN_SEGMENT_DIGITS.iter()) {
+        *r = SignalLine::from_string(s);
+    }
+    result
+}
 
-    let mut unsolved_patterns = entry.patterns.to_vec();
-
-    // start with the (easy) unique counts
-    for p in unsolved_patterns.iter() {
-        match p.iter().count() {
-            2 => result[1] = *p,
-            3 => result[7] = *p,
-            4 => result[4] = *p,
-            7 => result[8] = *p,
-            _ => (),
-        };
-    }
-    unsolved_patterns.retain(|p| !result.contains(p));
-
-    // wire a shows up in 8 patterns (7 and 8 unique)
-    // wire b shows up in 6 patterns (4 and 8 unique)         -- can determine
-    // wire c shows up in 8 patterns (all unique)
-    // wire d shows up in 7 patterns (4 and 8 unique)
-    // wire e shows up in 4 patterns (8 unique)               -- can determine
-    // wire f shows up in 9 patterns (all unique)             -- can determine
-    // wire g shows up in 7 patterns (8 unique)
-
-    // WIRE     TOTAL / REMAINING
-    // a        8 / 6
-    // b        6 / 4
-    // c        8 / 4
-    // d        7 / 5
-    // e        4 / 3
-    // f        9 / 5
-    // g        7 / 6
-
-    let wires = [
-        SignalLine::A,
-        SignalLine::B,
-        SignalLine::C,
-        SignalLine::D,
-        SignalLine::E,
-        SignalLine::F,
-        SignalLine::G,
-    ];
-    let counts = wires
-        .iter()
-        .map(|w| {
-            (
-                *w,
-                entry.patterns.iter().filter(|p| p.contains(*w)).count(),
-                unsolved_patterns.iter().filter(|p| p.contains(*w)).count(),
-            )
-        })
-        .collect_vec();
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SolveError<T: SegmentLine = SignalLine> {
+    /// No wire permutation maps the patterns onto the alphabet.
+    Inconsistent,
+    /// More than one wire permutation maps the patterns onto the alphabet.
+    Ambiguous,
+    /// A pattern does not correspond to any glyph under the solved wiring.
+    UnknownPattern(BitFlags<T>),
+}
 
-    let a = counts
-        .iter()
-        .find(|(_wire, t, r)| *t == 8 && *r == 6)
-        .unwrap()
-        .0;
-    let b = counts
-        .iter()
-        .find(|(_wire, t, r)| *t == 6 && *r == 4)
-        .unwrap()
-        .0;
-    let c = counts
-        .iter()
-        .find(|(_wire, t, r)| *t == 8 && *r == 4)
-        .unwrap()
-        .0;
-    let d = counts
-        .iter()
-        .find(|(_wire, t, r)| *t == 7 && *r == 5)
-        .unwrap()
-        .0;
-    let e = counts
-        .iter()
-        .find(|(_wire, t, r)| *t == 4 && *r == 3)
-        .unwrap()
-        .0;
-    let f = counts
-        .iter()
-        .find(|(_wire, t, r)| *t == 9 && *r == 5)
-        .unwrap()
-        .0;
-    let g = counts
-        .iter()
-        .find(|(_wire, t, r)| *t == 7 && *r == 6)
-        .unwrap()
-        .0;
+impl<T: SegmentLine> std::fmt::Display for SolveError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SolveError::Inconsistent => write!(f, "no wiring is consistent with the patterns"),
+            SolveError::Ambiguous => write!(f, "more than one wiring fits the patterns"),
+            SolveError::UnknownPattern(p) => write!(f, "pattern {:?} is not a known glyph", p),
+        }
+    }
+}
 
-    // NEED TO FIND:
-    // 0, 2, 3, 5, 6, 9
+impl<T: SegmentLine> std::error::Error for SolveError<T> {}
 
-    result[0] = a | b | c | e | f | g;
-    result[2] = a | c | d | e | g;
-    result[3] = a | c | d | f | g;
-    result[5] = a | b | d | f | g;
-    result[6] = a | b | d | e | f | g;
-    result[9] = a | b | c | d | f | g;
+/// Maps each scrambled wire (in declaration order) to the segment it actually drives.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Wiring<T: SegmentLine = SignalLine>(pub Vec<T>);
 
-    result
+impl<T: SegmentLine> Wiring<T> {
+    pub fn apply(&self, signal: BitFlags<T>) -> BitFlags<T> {
+        let mut result = BitFlags::default();
+        for (wire, &segment) in BitFlags::<T>::all().iter().zip(self.0.iter()) {
+            if signal.contains(wire) {
+                result |= segment;
+            }
+        }
+        result
+    }
+
+    pub fn invert(&self, segments: BitFlags<T>) -> BitFlags<T> {
+        let mut result = BitFlags::default();
+        for (wire, &segment) in BitFlags::<T>::all().iter().zip(self.0.iter()) {
+            if segments.contains(segment) {
+                result |= wire;
+            }
+        }
+        result
+    }
 }
 
-pub fn get_digit(signal: BitFlags<SignalLine>, map: &[BitFlags<SignalLine>]) -> usize {
-    for (i, s) in map.iter().enumerate() {
-        if *s == signal {
-            return i;
+fn line_name<T: SegmentLine>(line: T) -> char {
+    let idx = BitFlags::<T>::all().iter().position(|l| l == line).unwrap();
+    (b'a' + idx as u8) as char
+}
+
+fn lines_name<T: SegmentLine>(lines: BitFlags<T>) -> String {
+    lines.iter().map(line_name).collect()
+}
+
+/// One deduction made by the wiring solver. Glyphs are indices into the alphabet.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Step<T: SegmentLine = SignalLine> {
+    UniqueLength {
+        pattern: BitFlags<T>,
+        glyph: usize,
+    },
+    OccurrenceCount {
+        wire: T,
+        count: usize,
+        segments: BitFlags<T>,
+    },
+    GlyphIdentified {
+        pattern: BitFlags<T>,
+        glyph: usize,
+    },
+    WirePinned {
+        wire: T,
+        segment: T,
+    },
+    Guess {
+        wire: T,
+        segment: T,
+    },
+    Contradiction,
+    Solved,
+}
+
+impl<T: SegmentLine> std::fmt::Display for Step<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Step::UniqueLength { pattern, glyph } => write!(
+                f,
+                "pattern {} has {} lines, only glyph {} has that many segments",
+                lines_name(*pattern),
+                pattern.len(),
+                glyph
+            ),
+            Step::OccurrenceCount {
+                wire,
+                count,
+                segments,
+            } => write!(
+                f,
+                "wire {} appears in {} patterns, so it can only drive {}",
+                line_name(*wire),
+                count,
+                lines_name(*segments)
+            ),
+            Step::GlyphIdentified { pattern, glyph } => write!(
+                f,
+                "pattern {} can only be glyph {}",
+                lines_name(*pattern),
+                glyph
+            ),
+            Step::WirePinned { wire, segment } => write!(
+                f,
+                "wire {} drives segment {}",
+                line_name(*wire),
+                line_name(*segment)
+            ),
+            Step::Guess { wire, segment } => write!(
+                f,
+                "guess: wire {} drives segment {}",
+                line_name(*wire),
+                line_name(*segment)
+            ),
+            Step::Contradiction => write!(f, "contradiction, backtracking"),
+            Step::Solved => write!(f, "solved"),
         }
     }
-    panic!("Could not find digit!");
 }
 
-#[aoc(day8, part2)]
-pub fn solve_day8_part2(input: &[Entry]) -> usize {
-    input
+/// Deductions made while solving, in order, including abandoned guesses.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Trace<T: SegmentLine = SignalLine> {
+    enabled: bool,
+    pub steps: Vec<Step<T>>,
+}
+
+impl<T: SegmentLine> Trace<T> {
+    fn new(enabled: bool) -> Self {
+        Trace {
+            enabled,
+            steps: vec![],
+        }
+    }
+
+    fn record<F: FnOnce() -> Step<T>>(&mut self, step: F) {
+        if self.enabled {
+            self.steps.push(step());
+        }
+    }
+}
+
+impl<T: SegmentLine> std::fmt::Display for Trace<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, step) in self.steps.iter().enumerate() {
+            writeln!(f, "{:>3}. {}", i + 1, step)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+struct SolverState<T: SegmentLine> {
+    // possible segments per scrambled wire
+    candidates: Vec<BitFlags<T>>,
+    // possible alphabet indices per pattern
+    glyphs: Vec<Vec<usize>>,
+}
+
+impl<T: SegmentLine> SolverState<T> {
+    fn new(patterns: &[BitFlags<T>], alphabet: &[BitFlags<T>], trace: &mut Trace<T>) -> Self {
+        let wires = BitFlags::<T>::all().iter().collect_vec();
+        let mut candidates = vec![BitFlags::all(); wires.len()];
+
+        let glyphs = patterns
+            .iter()
+            .map(|p| {
+                let options = (0..alphabet.len())
+                    .filter(|&g| alphabet[g].len() == p.len())
+                    .collect_vec();
+                if options.len() == 1 {
+                    trace.record(|| Step::UniqueLength {
+                        pattern: *p,
+                        glyph: options[0],
+                    });
+                }
+                options
+            })
+            .collect_vec();
+
+        // with every glyph shown exactly once, a wire appears in as many patterns as its
+        // segment appears in glyphs
+        let distinct = patterns.iter().unique().count();
+        if distinct == patterns.len() && distinct == alphabet.len() {
+            for (&w, c) in wires.iter().zip(candidates.iter_mut()) {
+                let count = patterns.iter().filter(|p| p.contains(w)).count();
+                let mut segments = BitFlags::default();
+                for &s in wires.iter() {
+                    if alphabet.iter().filter(|g| g.contains(s)).count() == count {
+                        segments |= s;
+                    }
+                }
+                if segments.len() < wires.len() {
+                    trace.record(|| Step::OccurrenceCount {
+                        wire: w,
+                        count,
+                        segments,
+                    });
+                }
+                *c = segments;
+            }
+        }
+
+        SolverState { candidates, glyphs }
+    }
+
+    fn propagate(
+        &mut self,
+        patterns: &[BitFlags<T>],
+        alphabet: &[BitFlags<T>],
+        trace: &mut Trace<T>,
+    ) -> Result<(), SolveError<T>> {
+        let wires = BitFlags::<T>::all().iter().collect_vec();
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for (p, options) in patterns.iter().zip(self.glyphs.iter_mut()) {
+                let candidates = &self.candidates;
+                let before = options.len();
+                options.retain(|&g| {
+                    let glyph = alphabet[g];
+                    glyph.len() == p.len()
+                        && wires.iter().zip(candidates.iter()).all(|(&w, &c)| {
+                            if p.contains(w) {
+                                c.intersects(glyph)
+                            } else {
+                                c.intersects(!glyph)
+                            }
+                        })
+                });
+                if options.is_empty() {
+                    return Err(SolveError::Inconsistent);
+                }
+                if options.len() != before {
+                    changed = true;
+                    if options.len() == 1 {
+                        trace.record(|| Step::GlyphIdentified {
+                            pattern: *p,
+                            glyph: options[0],
+                        });
+                    }
+                }
+            }
+
+            // distinct patterns must show distinct glyphs
+            for i in 0..patterns.len() {
+                if self.glyphs[i].len() != 1 {
+                    continue;
+                }
+                let g = self.glyphs[i][0];
+                for j in 0..patterns.len() {
+                    if patterns[j] != patterns[i] && self.glyphs[j].contains(&g) {
+                        self.glyphs[j].retain(|&o| o != g);
+                        match self.glyphs[j].len() {
+                            0 => return Err(SolveError::Inconsistent),
+                            1 => trace.record(|| Step::GlyphIdentified {
+                                pattern: patterns[j],
+                                glyph: self.glyphs[j][0],
+                            }),
+                            _ => (),
+                        }
+                        changed = true;
+                    }
+                }
+            }
+
+            for (p, options) in patterns.iter().zip(self.glyphs.iter()) {
+                for (&w, c) in wires.iter().zip(self.candidates.iter_mut()) {
+                    let mut allowed = BitFlags::default();
+                    for &g in options {
+                        allowed |= if p.contains(w) {
+                            alphabet[g]
+                        } else {
+                            !alphabet[g]
+                        };
+                    }
+                    let narrowed = *c & allowed;
+                    if narrowed.is_empty() {
+                        return Err(SolveError::Inconsistent);
+                    }
+                    if narrowed != *c {
+                        changed = true;
+                        *c = narrowed;
+                        if let Some(segment) = narrowed.exactly_one() {
+                            trace.record(|| Step::WirePinned { wire: w, segment });
+                        }
+                    }
+                }
+            }
+
+            // each segment is driven by exactly one wire
+            for i in 0..wires.len() {
+                if self.candidates[i].len() != 1 {
+                    continue;
+                }
+                let pinned = self.candidates[i];
+                for (j, (&w, c)) in wires.iter().zip(self.candidates.iter_mut()).enumerate() {
+                    if i != j && c.intersects(pinned) {
+                        *c &= !pinned;
+                        if c.is_empty() {
+                            return Err(SolveError::Inconsistent);
+                        }
+                        if let Some(segment) = c.exactly_one() {
+                            trace.record(|| Step::WirePinned { wire: w, segment });
+                        }
+                        changed = true;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn search(
+        mut self,
+        patterns: &[BitFlags<T>],
+        alphabet: &[BitFlags<T>],
+        solutions: &mut Vec<Wiring<T>>,
+        trace: &mut Trace<T>,
+    ) {
+        if self.propagate(patterns, alphabet, trace).is_err() {
+            trace.record(|| Step::Contradiction);
+            return;
+        }
+
+        let branch = (0..self.candidates.len())
+            .filter(|&i| self.candidates[i].len() > 1)
+            .min_by_key(|&i| self.candidates[i].len());
+
+        match branch {
+            None => {
+                let wiring = Wiring(
+                    self.candidates
+                        .iter()
+                        .map(|c| c.iter().next().unwrap())
+                        .collect(),
+                );
+                let valid = patterns
+                    .iter()
+                    .all(|&p| alphabet.contains(&wiring.apply(p)));
+                if !valid {
+                    trace.record(|| Step::Contradiction);
+                } else if !solutions.contains(&wiring) {
+                    trace.record(|| Step::Solved);
+                    solutions.push(wiring);
+                }
+            }
+            Some(i) => {
+                let wire = BitFlags::<T>::all().iter().nth(i).unwrap();
+                for segment in self.candidates[i].iter() {
+                    if solutions.len() > 1 {
+                        return;
+                    }
+                    trace.record(|| Step::Guess { wire, segment });
+                    let mut next = self.clone();
+                    next.candidates[i] = segment.into();
+                    next.search(patterns, alphabet, solutions, trace);
+                }
+            }
+        }
+    }
+}
+
+fn solve_wiring_with_trace<T: SegmentLine>(
+    patterns: &[BitFlags<T>],
+    alphabet: &[BitFlags<T>],
+    trace: &mut Trace<T>,
+) -> Result<Wiring<T>, SolveError<T>> {
+    let state = SolverState::new(patterns, alphabet, trace);
+    let mut solutions = vec![];
+    state.search(patterns, alphabet, &mut solutions, trace);
+    match solutions.len() {
+        0 => Err(SolveError::Inconsistent),
+        1 => Ok(solutions.remove(0)),
+        _ => Err(SolveError::Ambiguous),
+    }
+}
+
+/// Finds the unique wire permutation under which every pattern is a glyph of `alphabet`.
+pub fn solve_wiring<T: SegmentLine>(
+    patterns: &[BitFlags<T>],
+    alphabet: &[BitFlags<T>],
+) -> Result<Wiring<T>, SolveError<T>> {
+    solve_wiring_with_trace(patterns, alphabet, &mut Trace::new(false))
+}
+
+/// As `solve_wiring`, also returning every deduction the solver made.
+pub fn solve_wiring_traced<T: SegmentLine>(
+    patterns: &[BitFlags<T>],
+    alphabet: &[BitFlags<T>],
+) -> (Result<Wiring<T>, SolveError<T>>, Trace<T>) {
+    let mut trace = Trace::new(true);
+    let result = solve_wiring_with_trace(patterns, alphabet, &mut trace);
+    (result, trace)
+}
+
+/// Decodes the output of an entry for any display, given the font of glyphs it can show.
+pub fn decode_symbols<T: SegmentLine>(
+    entry: &Entry<T>,
+    font: &[(char, BitFlags<T>)],
+) -> Result<String, SolveError<T>> {
+    let alphabet = font.iter().map(|g| g.1).collect_vec();
+    let wiring = solve_wiring(&entry.patterns, &alphabet)?;
+    entry
+        .output
         .iter()
-        .map(|e| {
-            let map = solve_map(e);
-            get_digit(e.output[0], &map) * 1000
-                + get_digit(e.output[1], &map) * 100
-                + get_digit(e.output[2], &map) * 10
-                + get_digit(e.output[3], &map)
+        .map(|&signal| {
+            let segments = wiring.apply(signal);
+            font.iter()
+                .find(|g| g.1 == segments)
+                .map(|g| g.0)
+                .ok_or(SolveError::UnknownPattern(signal))
         })
-        .sum()
+        .collect()
+}
+
+fn digit_map(wiring: &Wiring) -> [BitFlags<SignalLine>; 10] {
+    let mut result = [BitFlags::default(); 10];
+    for (r, &glyph) in result.iter_mut().zip(seven_segment_alphabet().iter()) {
+        *r = wiring.invert(glyph);
+    }
+    result
+}
+
+/// Scrambled pattern for each digit of the standard seven-segment alphabet.
+pub fn solve_map(entry: &Entry) -> Result<[BitFlags<SignalLine>; 10], SolveError> {
+    let wiring = solve_wiring(&entry.patterns, &seven_segment_alphabet())?;
+    Ok(digit_map(&wiring))
+}
+
+/// As `solve_map`, also returning the deductions that led to it.
+pub fn solve_map_traced(entry: &Entry) -> (Result<[BitFlags<SignalLine>; 10], SolveError>, Trace) {
+    let (wiring, trace) = solve_wiring_traced(&entry.patterns, &seven_segment_alphabet());
+    (wiring.map(|w| digit_map(&w)), trace)
+}
+
+pub fn decode_output(entry: &Entry) -> Result<usize, SolveError> {
+    let map = solve_map(entry)?;
+    entry.output.iter().try_fold(0, |acc, &signal| {
+        let digit = map
+            .iter()
+            .position(|&s| s == signal)
+            .ok_or(SolveError::UnknownPattern(signal))?;
+        Ok(acc * 10 + digit)
+    })
+}
+
+/// Renders a set of lines in a random order, e.g. `fbc`.
+fn scrambled_word(rng: &mut SplitMix64, signal: BitFlags<SignalLine>) -> String {
+    let mut chars = signal.iter().map(line_name).collect_vec();
+    rng.shuffle(&mut chars);
+    chars.into_iter().collect()
+}
+
+/// Generates one day 8 input line per number, each with its own random wiring,
+/// shuffled pattern order and shuffled letters, along with the value it should decode to.
+pub fn generate_entries(seed: u64, numbers: &[usize]) -> Vec<(String, usize)> {
+    let mut rng = SplitMix64::new(seed);
+    let alphabet = seven_segment_alphabet();
+
+    numbers
+        .iter()
+        .map(|&n| {
+            assert!(n < 10_000, "{} does not fit on a 4-digit display", n);
+
+            let mut segments = BitFlags::<SignalLine>::all().iter().collect_vec();
+            rng.shuffle(&mut segments);
+            let wiring = Wiring(segments);
+
+            let mut order = (0..alphabet.len()).collect_vec();
+            rng.shuffle(&mut order);
+            let patterns = order
+                .iter()
+                .map(|&d| scrambled_word(&mut rng, wiring.invert(alphabet[d])))
+                .join(" ");
+            let output = [n / 1000, n / 100 % 10, n / 10 % 10, n % 10]
+                .iter()
+                .map(|&d| scrambled_word(&mut rng, wiring.invert(alphabet[d])))
+                .join(" ");
+
+            (format!("{} | {}", patterns, output), n)
+        })
+        .collect()
+}
+
+#[aoc(day8, part2)]
+pub fn solve_day8_part2(input: &[Entry]) -> Result<usize, SolveError> {
+    input.iter().map(decode_output).sum()
 }
 
 #[cfg(test)]
@@ -249,15 +796,15 @@ gcafb gcf dcaebfg ecagb gf abcdeg gaef cafbge fdbac fegbdc | fgae cfgab fg bagce
 
     #[test]
     fn test_day8_part1() {
-        let data = input_generator_day8(&DATA);
+        let data = input_generator_day8(DATA);
         let result = solve_day8_part1(&data);
         assert_eq!(result, 26);
     }
 
     #[test]
     fn test_solve_map() {
-        let data = input_generator_day8(&SHORT_DATA);
-        let map = solve_map(&data[0]);
+        let data = input_generator_day8(SHORT_DATA);
+        let map = solve_map(&data[0]).unwrap();
         let correct = [
             SignalLine::from_string("cagedb"),
             SignalLine::from_string("ab"),
@@ -275,8 +822,186 @@ gcafb gcf dcaebfg ecagb gf abcdeg gaef cafbge fdbac fegbdc | fgae cfgab fg bagce
 
     #[test]
     fn test_day8_part2() {
-        let data = input_generator_day8(&DATA);
+        let data = input_generator_day8(DATA);
         let result = solve_day8_part2(&data);
-        assert_eq!(result, 61229);
+        assert_eq!(result, Ok(61229));
+    }
+
+    #[test]
+    fn test_solve_wiring() {
+        let data = input_generator_day8(SHORT_DATA);
+        let wiring = solve_wiring(&data[0].patterns, &seven_segment_alphabet()).unwrap();
+        // "ab" is the scrambled 1, so a and b drive c and f
+        assert_eq!(
+            wiring.apply(SignalLine::from_string("ab")),
+            SignalLine::from_string("cf")
+        );
+        assert_eq!(decode_output(&data[0]), Ok(5353));
+    }
+
+    #[test]
+    fn test_generate_entries() {
+        let generated = generate_entries(2021, &[0, 7, 1234, 9999, 5353]);
+        assert_eq!(generated, generate_entries(2021, &[0, 7, 1234, 9999, 5353]));
+        assert_ne!(generated, generate_entries(2022, &[0, 7, 1234, 9999, 5353]));
+
+        let input = generated.iter().map(|g| g.0.as_str()).join("\n");
+        let data = input_generator_day8(&input);
+        for (entry, (_, expected)) in data.iter().zip(generated.iter()) {
+            assert_eq!(entry.patterns.len(), 10);
+            assert_eq!(entry.output.len(), 4);
+            assert_eq!(decode_output(entry), Ok(*expected));
+        }
+    }
+
+    #[test]
+    fn test_solve_inverts_any_scrambling() {
+        for seed in 0..200 {
+            let numbers = (0..5)
+                .map(|i| (seed as usize * 7919 + i * 104_729) % 10_000)
+                .collect_vec();
+            let generated = generate_entries(seed, &numbers);
+            let input = generated.iter().map(|g| g.0.as_str()).join("\n");
+            let data = input_generator_day8(&input);
+            for entry in &data {
+                let map = solve_map(entry).unwrap();
+                let mut patterns = entry.patterns.clone();
+                patterns.sort_by_key(|p| p.bits());
+                let mut solved = map.to_vec();
+                solved.sort_by_key(|p| p.bits());
+                assert_eq!(patterns, solved);
+            }
+            assert_eq!(solve_day8_part2(&data), Ok(numbers.iter().sum()));
+        }
+    }
+
+    fn scramble<T: SegmentLine>(
+        text: &str,
+        font: &[(char, BitFlags<T>)],
+        shift: usize,
+    ) -> Entry<T> {
+        // rotate wire i to wire i + shift, and show every glyph in reverse order
+        let wires = BitFlags::<T>::all().iter().collect_vec();
+        let wiring = Wiring(
+            (0..wires.len())
+                .map(|i| wires[(i + wires.len() - shift) % wires.len()])
+                .collect(),
+        );
+        let encode = |segments: BitFlags<T>| wiring.invert(segments);
+        Entry {
+            patterns: font.iter().rev().map(|g| encode(g.1)).collect(),
+            output: text
+                .chars()
+                .map(|ch| encode(font.iter().find(|g| g.0 == ch).unwrap().1))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_fonts_are_distinct() {
+        let font = FourteenSegment::font(&FOURTEEN_SEGMENT_FONT);
+        assert_eq!(font.iter().map(|g| g.1).unique().count(), font.len());
+        let font = SixteenSegment::font(&SIXTEEN_SEGMENT_FONT);
+        assert_eq!(font.iter().map(|g| g.1).unique().count(), font.len());
+    }
+
+    #[test]
+    fn test_decode_fourteen_segment() {
+        let font = FourteenSegment::font(&FOURTEEN_SEGMENT_FONT);
+        let entry = scramble("HELLO2021", &font, 5);
+        assert_eq!(decode_symbols(&entry, &font), Ok("HELLO2021".to_string()));
+
+        let line = "bc adef abcdef | adef bc";
+        let entries = parse_entries::<FourteenSegment>(line);
+        assert_eq!(entries[0].patterns.len(), 3);
+        assert_eq!(
+            decode_symbols(&entries[0], &font),
+            Err(SolveError::Ambiguous)
+        );
+    }
+
+    #[test]
+    fn test_decode_sixteen_segment() {
+        let font = SixteenSegment::font(&SIXTEEN_SEGMENT_FONT);
+        let entry = scramble("fun2CODE", &font, 11);
+        assert_eq!(decode_symbols(&entry, &font), Ok("fun2CODE".to_string()));
+    }
+
+    #[test]
+    fn test_decode_seven_segment_font() {
+        let data = input_generator_day8(SHORT_DATA);
+        let font = SignalLine::font(&[
+            ('0', SEVEN_SEGMENT_DIGITS[0]),
+            ('1', SEVEN_SEGMENT_DIGITS[1]),
+            ('2', SEVEN_SEGMENT_DIGITS[2]),
+            ('3', SEVEN_SEGMENT_DIGITS[3]),
+            ('4', SEVEN_SEGMENT_DIGITS[4]),
+            ('5', SEVEN_SEGMENT_DIGITS[5]),
+            ('6', SEVEN_SEGMENT_DIGITS[6]),
+            ('7', SEVEN_SEGMENT_DIGITS[7]),
+            ('8', SEVEN_SEGMENT_DIGITS[8]),
+            ('9', SEVEN_SEGMENT_DIGITS[9]),
+        ]);
+        assert_eq!(decode_symbols(&data[0], &font), Ok("5353".to_string()));
+    }
+
+    #[test]
+    fn test_solve_map_traced() {
+        let data = input_generator_day8(SHORT_DATA);
+        let (map, trace) = solve_map_traced(&data[0]);
+        assert_eq!(map, solve_map(&data[0]));
+        assert!(trace.steps.contains(&Step::UniqueLength {
+            pattern: SignalLine::from_string("ab"),
+            glyph: 1
+        }));
+        assert!(trace.steps.contains(&Step::OccurrenceCount {
+            wire: SignalLine::G,
+            count: 4,
+            segments: SignalLine::E.into()
+        }));
+        assert_eq!(trace.steps.last(), Some(&Step::Solved));
+        assert!(!trace.steps.iter().any(|s| matches!(s, Step::Guess { .. })));
+
+        let text = trace.to_string();
+        assert!(text.contains("pattern ab has 2 lines, only glyph 1 has that many segments"));
+        assert!(text.contains("wire g appears in 4 patterns, so it can only drive e"));
+    }
+
+    #[test]
+    fn test_solve_wiring_traced_failure() {
+        let mut entry = input_generator_day8(SHORT_DATA)[0].clone();
+        entry.patterns[9] = SignalLine::from_string("cd");
+        let (map, trace) = solve_map_traced(&entry);
+        assert_eq!(map, Err(SolveError::Inconsistent));
+        assert!(trace.steps.contains(&Step::Contradiction));
+        assert!(solve_wiring(&entry.patterns, &seven_segment_alphabet()).is_err());
+    }
+
+    #[test]
+    fn test_solve_wiring_errors() {
+        let alphabet = seven_segment_alphabet();
+
+        // only 1 and 7 known: many wirings fit
+        let patterns = [
+            SignalLine::from_string("ab"),
+            SignalLine::from_string("dab"),
+        ];
+        assert_eq!(
+            solve_wiring(&patterns, &alphabet),
+            Err(SolveError::Ambiguous)
+        );
+
+        // two different two-segment patterns cannot both be 1
+        let mut entry = input_generator_day8(SHORT_DATA)[0].clone();
+        entry.patterns[9] = SignalLine::from_string("cd");
+        assert_eq!(solve_map(&entry), Err(SolveError::Inconsistent));
+
+        // output not among the glyphs
+        let mut entry = input_generator_day8(SHORT_DATA)[0].clone();
+        entry.output[0] = SignalLine::from_string("abc");
+        assert_eq!(
+            decode_output(&entry),
+            Err(SolveError::UnknownPattern(SignalLine::from_string("abc")))
+        );
     }
 }
@@ -1,6 +1,8 @@
+use crate::helper::DisjointSet;
 use aoc_runner_derive::{aoc, aoc_generator};
 use itertools::Itertools;
-use std::borrow::Borrow;
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Cell {
@@ -10,12 +12,56 @@ pub struct Cell {
     basin_id: i32,
 }
 
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Neighbourhood {
+    /// Orthogonal neighbours only
+    Four,
+    /// Orthogonal and diagonal neighbours
+    Eight,
+    /// Hexagonal grid in "odd-r" layout, where odd rows are shifted half a cell right.
+    /// Wrapping requires an even height, so row parity survives the wrap.
+    Hex,
+}
+
+impl Neighbourhood {
+    fn offsets(self, y: usize) -> &'static [(i64, i64)] {
+        match self {
+            Neighbourhood::Four => &[(-1, 0), (0, -1), (1, 0), (0, 1)],
+            Neighbourhood::Eight => &[
+                (-1, -1),
+                (0, -1),
+                (1, -1),
+                (-1, 0),
+                (1, 0),
+                (-1, 1),
+                (0, 1),
+                (1, 1),
+            ],
+            Neighbourhood::Hex if y % 2 == 1 => {
+                &[(-1, 0), (1, 0), (0, -1), (1, -1), (0, 1), (1, 1)]
+            }
+            Neighbourhood::Hex => &[(-1, 0), (1, 0), (-1, -1), (0, -1), (-1, 1), (0, 1)],
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Boundary {
+    /// Cells on the edge have fewer neighbours
+    Clamped,
+    /// Edges wrap around to the opposite side, as on a torus
+    Wrapping,
+}
+
 #[derive(Clone, Debug)]
 pub struct Map {
     map: Vec<Vec<u8>>,
     basin_ids: Vec<Vec<i32>>,
     width: usize,
     height: usize,
+    neighbourhood: Neighbourhood,
+    boundary: Boundary,
+    wall_height: u8,
 }
 
 impl Map {
@@ -40,44 +86,83 @@ impl Map {
         x < self.width && y < self.height
     }
 
-    pub fn get_adjacent_cells(&self, x: usize, y: usize) -> Vec<Cell> {
-        let mut result = vec![];
+    /// Panics if a hexagonal neighbourhood would wrap a map of odd height.
+    pub fn with_neighbourhood(mut self, neighbourhood: Neighbourhood) -> Self {
+        self.neighbourhood = neighbourhood;
+        self.assert_topology();
+        self
+    }
 
-        if x > 0 {
-            result.push(self.get_cell(x - 1, y));
-        }
-        if y > 0 {
-            result.push(self.get_cell(x, y - 1));
-        }
-        if x < self.width - 1 {
-            result.push(self.get_cell(x + 1, y));
-        }
-        if y < self.height - 1 {
-            result.push(self.get_cell(x, y + 1));
-        }
+    /// Panics if a hexagonal neighbourhood would wrap a map of odd height.
+    pub fn with_boundary(mut self, boundary: Boundary) -> Self {
+        self.boundary = boundary;
+        self.assert_topology();
+        self
+    }
 
-        result
+    // with an odd height, wrapping joins two rows of the same parity and hex adjacency is
+    // no longer symmetric
+    fn assert_topology(&self) {
+        assert!(
+            !(self.neighbourhood == Neighbourhood::Hex
+                && self.boundary == Boundary::Wrapping
+                && self.height % 2 == 1),
+            "hexagonal wrapping needs an even map height, got {}",
+            self.height
+        );
     }
 
-    pub fn get_adjacent_values(&self, x: usize, y: usize) -> Vec<u8> {
+    /// Cells at or above `wall_height` separate basins; the default is 9.
+    pub fn with_wall_height(mut self, wall_height: u8) -> Self {
+        self.wall_height = wall_height;
+        self
+    }
+
+    pub fn is_wall(&self, value: u8) -> bool {
+        value >= self.wall_height
+    }
+
+    pub fn get_adjacent_positions(&self, x: usize, y: usize) -> Vec<(usize, usize)> {
+        let (w, h) = (self.width as i64, self.height as i64);
         let mut result = vec![];
 
-        if x > 0 {
-            result.push(self.get(x - 1, y));
-        }
-        if y > 0 {
-            result.push(self.get(x, y - 1));
-        }
-        if x < self.width - 1 {
-            result.push(self.get(x + 1, y));
-        }
-        if y < self.height - 1 {
-            result.push(self.get(x, y + 1));
+        for &(dx, dy) in self.neighbourhood.offsets(y) {
+            let (mut ax, mut ay) = (x as i64 + dx, y as i64 + dy);
+            match self.boundary {
+                Boundary::Clamped => {
+                    if ax < 0 || ay < 0 || ax >= w || ay >= h {
+                        continue;
+                    }
+                }
+                Boundary::Wrapping => {
+                    ax = ax.rem_euclid(w);
+                    ay = ay.rem_euclid(h);
+                }
+            }
+            // on tiny wrapping maps several offsets can land on the same cell
+            let pos = (ax as usize, ay as usize);
+            if pos != (x, y) && !result.contains(&pos) {
+                result.push(pos);
+            }
         }
 
         result
     }
 
+    pub fn get_adjacent_cells(&self, x: usize, y: usize) -> Vec<Cell> {
+        self.get_adjacent_positions(x, y)
+            .into_iter()
+            .map(|(ax, ay)| self.get_cell(ax, ay))
+            .collect()
+    }
+
+    pub fn get_adjacent_values(&self, x: usize, y: usize) -> Vec<u8> {
+        self.get_adjacent_positions(x, y)
+            .into_iter()
+            .map(|(ax, ay)| self.get(ax, ay))
+            .collect()
+    }
+
     pub fn is_local_min(&self, cell: Cell) -> bool {
         for adj in self.get_adjacent_values(cell.x, cell.y) {
             if adj <= cell.value {
@@ -87,29 +172,315 @@ impl Map {
         true
     }
 
+    /// Labels every connected region of non-wall cells with a basin id, numbered in
+    /// row-major order of their first cell. Walls keep the id -1.
     pub fn fill_basin_ids(&mut self) {
-        let mut basins = vec![];
+        let width = self.width;
+        let index = move |x: usize, y: usize| y * width + x;
+        let mut sets = DisjointSet::new(self.width * self.height);
+        for cell in self.into_iter().filter(|c| !self.is_wall(c.value)) {
+            for adj in self.get_adjacent_cells(cell.x, cell.y) {
+                if !self.is_wall(adj.value) {
+                    sets.union(index(cell.x, cell.y), index(adj.x, adj.y));
+                }
+            }
+        }
+
+        let mut ids = vec![-1; self.width * self.height];
+        let mut next_id = 0;
         for y in 0..self.height {
             for x in 0..self.width {
-                let cell = self.get_cell(x, y);
-                if self.is_local_min(cell) {
-                    basins.push(cell);
+                if self.is_wall(self.get(x, y)) {
+                    self.set_basin_id(x, y, -1);
+                    continue;
+                }
+                let root = sets.find(index(x, y));
+                if ids[root] == -1 {
+                    ids[root] = next_id;
+                    next_id += 1;
                 }
+                self.set_basin_id(x, y, ids[root]);
             }
         }
+    }
 
-        for (id, cell) in basins.iter().enumerate() {
-            let mut to_do = vec![*cell];
+    /// Assigns every non-wall cell to the basin of the low point it drains to, always
+    /// flowing to the lowest neighbour. Flat regions drain towards their nearest exit, and
+    /// flat regions without an exit are low points themselves.
+    pub fn fill_watershed_ids(&mut self) -> WatershedReport {
+        let width = self.width;
+        let index = move |x: usize, y: usize| y * width + x;
+        let len = self.width * self.height;
 
-            while !to_do.is_empty() {
-                let check = to_do.pop().unwrap();
-                if check.value != 9 && check.basin_id == -1 {
-                    self.set_basin_id(check.x, check.y, id as i32);
-                    let mut adjacent_cells = self.get_adjacent_cells(check.x, check.y);
-                    to_do.append(&mut adjacent_cells);
+        // connected regions of equal height
+        let mut plateaus = DisjointSet::new(len);
+        for cell in self.into_iter().filter(|c| !self.is_wall(c.value)) {
+            for adj in self.get_adjacent_cells(cell.x, cell.y) {
+                if adj.value == cell.value {
+                    plateaus.union(index(cell.x, cell.y), index(adj.x, adj.y));
                 }
             }
         }
+
+        // where each cell drains to: its lowest strictly lower neighbours, or for cells in
+        // the middle of a flat region, the neighbours one step closer to its edge
+        let mut drains: Vec<Vec<usize>> = vec![vec![]; len];
+        let mut distance = vec![usize::MAX; len];
+        let mut queue = std::collections::VecDeque::new();
+        for cell in self.into_iter().filter(|c| !self.is_wall(c.value)) {
+            let i = index(cell.x, cell.y);
+            let lower = self
+                .get_adjacent_cells(cell.x, cell.y)
+                .into_iter()
+                .filter(|adj| adj.value < cell.value)
+                .collect_vec();
+            if let Some(lowest) = lower.iter().map(|adj| adj.value).min() {
+                drains[i] = lower
+                    .iter()
+                    .filter(|adj| adj.value == lowest)
+                    .map(|adj| index(adj.x, adj.y))
+                    .collect();
+                distance[i] = 0;
+                queue.push_back((cell.x, cell.y));
+            }
+        }
+        while let Some((x, y)) = queue.pop_front() {
+            let d = distance[index(x, y)];
+            for (ax, ay) in self.get_adjacent_positions(x, y) {
+                let a = index(ax, ay);
+                if self.get(ax, ay) != self.get(x, y) {
+                    continue;
+                }
+                if distance[a] == usize::MAX {
+                    distance[a] = d + 1;
+                    queue.push_back((ax, ay));
+                }
+                if distance[a] == d + 1 {
+                    drains[a].push(index(x, y));
+                }
+            }
+        }
+
+        // resolve low points from the bottom up, so every drain is resolved before its sources
+        let mut order = self
+            .into_iter()
+            .filter(|c| !self.is_wall(c.value))
+            .map(|c| (c.value, distance[index(c.x, c.y)], index(c.x, c.y)))
+            .collect_vec();
+        order.sort_unstable();
+
+        let mut sinks: Vec<Vec<usize>> = vec![vec![]; len];
+        for &(_, _, i) in &order {
+            sinks[i] = if drains[i].is_empty() {
+                vec![plateaus.find(i)]
+            } else {
+                drains[i]
+                    .iter()
+                    .flat_map(|&d| sinks[d].iter().copied())
+                    .unique()
+                    .collect()
+            };
+        }
+
+        let mut ids = vec![-1; len];
+        let mut next_id = 0;
+        let mut conflicts = vec![];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let i = index(x, y);
+                if self.is_wall(self.get(x, y)) {
+                    self.set_basin_id(x, y, -1);
+                    continue;
+                }
+                let sink = sinks[i][0];
+                if ids[sink] == -1 {
+                    ids[sink] = next_id;
+                    next_id += 1;
+                }
+                self.set_basin_id(x, y, ids[sink]);
+                if sinks[i].len() > 1 {
+                    conflicts.push(DrainConflict {
+                        cell: (x, y),
+                        low_points: sinks[i].iter().map(|&s| (s % width, s / width)).collect(),
+                    });
+                }
+            }
+        }
+
+        WatershedReport { conflicts }
+    }
+
+    fn cell_colour(&self, cell: Cell) -> Rgb {
+        if self.is_local_min(cell) && !self.is_wall(cell.value) {
+            LOW_POINT_COLOUR
+        } else if cell.basin_id < 0 {
+            WALL_COLOUR
+        } else {
+            basin_colour(cell.basin_id as usize)
+        }
+    }
+
+    /// The heightmap as 24-bit ANSI terminal art, one coloured digit per cell.
+    /// `fill_basin_ids` (or `fill_watershed_ids`) must have been called first.
+    pub fn render_ansi(&self) -> String {
+        let mut result = String::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let cell = self.get_cell(x, y);
+                let colour = self.cell_colour(cell);
+                let (r, g, b) = colour;
+                let fg = text_colour(colour);
+                result += &format!("\x1b[{};48;2;{};{};{}m{}", fg, r, g, b, cell.value);
+            }
+            result += "\x1b[0m\n";
+        }
+        result
+    }
+
+    /// Writes the basins as a binary PPM image with `scale` pixels per cell.
+    /// `fill_basin_ids` (or `fill_watershed_ids`) must have been called first.
+    pub fn write_ppm<W: Write>(&self, mut writer: W, scale: usize) -> io::Result<()> {
+        write!(
+            writer,
+            "P6\n{} {}\n255\n",
+            self.width * scale,
+            self.height * scale
+        )?;
+        let mut row = Vec::with_capacity(self.width * scale * 3);
+        for y in 0..self.height {
+            row.clear();
+            for x in 0..self.width {
+                let (r, g, b) = self.cell_colour(self.get_cell(x, y));
+                for _ in 0..scale {
+                    row.extend_from_slice(&[r, g, b]);
+                }
+            }
+            for _ in 0..scale {
+                writer.write_all(&row)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Summary of every basin; `fill_basin_ids` must have been called first.
+    pub fn basin_report(&self) -> BasinReport {
+        let mut basins: BTreeMap<usize, Basin> = BTreeMap::new();
+        for cell in self.into_iter().filter(|c| c.basin_id >= 0) {
+            let id = cell.basin_id as usize;
+            let basin = basins.entry(id).or_insert(Basin {
+                id,
+                low_point: (cell.x, cell.y),
+                low_value: cell.value,
+                size: 0,
+                bounds: BoundingBox {
+                    min_x: cell.x,
+                    min_y: cell.y,
+                    max_x: cell.x,
+                    max_y: cell.y,
+                },
+            });
+            basin.size += 1;
+            if cell.value < basin.low_value {
+                basin.low_value = cell.value;
+                basin.low_point = (cell.x, cell.y);
+            }
+            basin.bounds.min_x = basin.bounds.min_x.min(cell.x);
+            basin.bounds.min_y = basin.bounds.min_y.min(cell.y);
+            basin.bounds.max_x = basin.bounds.max_x.max(cell.x);
+            basin.bounds.max_y = basin.bounds.max_y.max(cell.y);
+        }
+        let basins = basins.into_values().collect();
+        BasinReport { basins }
+    }
+}
+
+pub type Rgb = (u8, u8, u8);
+
+pub const WALL_COLOUR: Rgb = (0, 0, 0);
+pub const LOW_POINT_COLOUR: Rgb = (255, 255, 255);
+
+/// A distinct, saturated colour for each basin id, stepping the hue by the golden angle.
+pub fn basin_colour(id: usize) -> Rgb {
+    let hue = (id as f64 * 137.507_764) % 360.0;
+    let (s, v) = (0.65, 0.85);
+    let c = v * s;
+    let h = hue / 60.0;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = v - c;
+    let channel = |n: f64| ((n + m) * 255.0).round() as u8;
+    (channel(r), channel(g), channel(b))
+}
+
+// ANSI foreground: dark digits on light backgrounds and vice versa
+fn text_colour((r, g, b): Rgb) -> u8 {
+    if (r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000 > 128 {
+        30
+    } else {
+        97
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct BoundingBox {
+    pub min_x: usize,
+    pub min_y: usize,
+    pub max_x: usize,
+    pub max_y: usize,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Basin {
+    pub id: usize,
+    /// Lowest cell of the basin (first in row-major order on ties), which need not be a
+    /// strict local minimum.
+    pub low_point: (usize, usize),
+    pub low_value: u8,
+    pub size: usize,
+    pub bounds: BoundingBox,
+}
+
+/// A cell whose lowest neighbours lead to more than one low point.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DrainConflict {
+    pub cell: (usize, usize),
+    /// One cell of each low point region the cell can drain to; the cell is assigned to
+    /// the basin of the first.
+    pub low_points: Vec<(usize, usize)>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WatershedReport {
+    pub conflicts: Vec<DrainConflict>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BasinReport {
+    pub basins: Vec<Basin>,
+}
+
+impl BasinReport {
+    pub fn largest(&self, k: usize) -> Vec<Basin> {
+        let mut basins = self.basins.clone();
+        basins.sort_by(|a, b| b.size.cmp(&a.size).then(a.id.cmp(&b.id)));
+        basins.truncate(k);
+        basins
+    }
+
+    /// Product of the sizes of the `k` largest basins, or `None` if there are fewer than `k`.
+    pub fn top_k_product(&self, k: usize) -> Option<usize> {
+        let largest = self.largest(k);
+        if largest.len() < k {
+            return None;
+        }
+        Some(largest.iter().map(|b| b.size).product())
     }
 }
 
@@ -122,6 +493,9 @@ impl From<Vec<Vec<u8>>> for Map {
             basin_ids: vec![vec![-1; width]; height],
             height,
             width,
+            neighbourhood: Neighbourhood::Four,
+            boundary: Boundary::Clamped,
+            wall_height: 9,
         }
     }
 }
@@ -175,6 +549,72 @@ pub fn input_generator_day9(input: &str) -> Map {
         .into()
 }
 
+fn parse_row(line: &str, row: &mut Vec<u8>) -> io::Result<()> {
+    row.clear();
+    for ch in line.trim_end().chars() {
+        let digit = ch.to_digit(10).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected character '{}'", ch),
+            )
+        })?;
+        row.push(digit as u8);
+    }
+    Ok(())
+}
+
+fn row_risk(above: Option<&[u8]>, row: &[u8], below: Option<&[u8]>) -> u64 {
+    (0..row.len())
+        .filter(|&x| {
+            let value = row[x];
+            (x == 0 || row[x - 1] > value)
+                && (x + 1 == row.len() || row[x + 1] > value)
+                && above.is_none_or(|a| a[x] > value)
+                && below.is_none_or(|b| b[x] > value)
+        })
+        .map(|x| row[x] as u64 + 1)
+        .sum()
+}
+
+/// Part 1 risk sum computed while reading, keeping only three rows in memory.
+///
+/// Uses the puzzle's topology (four neighbours, clamped edges).
+pub fn stream_risk_sum<R: BufRead>(mut reader: R) -> io::Result<u64> {
+    let mut line = String::new();
+    let mut above: Option<Vec<u8>> = None;
+    let mut current: Option<Vec<u8>> = None;
+    let mut below = vec![];
+    let mut total = 0;
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+        parse_row(&line, &mut below)?;
+
+        if let Some(row) = &current {
+            if row.len() != below.len() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "rows have different lengths",
+                ));
+            }
+            total += row_risk(above.as_deref(), row, Some(&below));
+        }
+
+        // rotate the buffers so that no row is reallocated
+        let recycled = above.take().unwrap_or_default();
+        above = current.take();
+        current = Some(std::mem::replace(&mut below, recycled));
+    }
+
+    if let Some(row) = &current {
+        total += row_risk(above.as_deref(), row, None);
+    }
+    Ok(total)
+}
+
 #[aoc(day9, part1)]
 pub fn solve_day9_part1(input: &Map) -> usize {
     input
@@ -188,28 +628,13 @@ pub fn solve_day9_part1(input: &Map) -> usize {
 pub fn solve_day9_part2(input: &Map) -> usize {
     let mut input = input.clone();
     input.fill_basin_ids();
-
-    let max_basin_id = input
-        .borrow()
-        .into_iter()
-        .map(|cell| cell.basin_id)
-        .max()
-        .unwrap();
-    let mut counts = vec![0; max_basin_id as usize + 1];
-    for cell in &input {
-        if cell.basin_id > 0 {
-            counts[cell.basin_id as usize] += 1;
-        }
-    }
-    let mut counts = counts.into_iter().enumerate().collect_vec();
-    counts.sort_by(|a, b| b.1.cmp(&a.1));
-
-    counts[0].1 * counts[1].1 * counts[2].1
+    input.basin_report().top_k_product(3).unwrap()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::helper::SplitMix64;
     use pretty_assertions::assert_eq;
 
     const DATA: &str = "2199943210
@@ -220,15 +645,290 @@ mod tests {
 
     #[test]
     fn test_day9_part1() {
-        let data = input_generator_day9(&DATA);
+        let data = input_generator_day9(DATA);
         let result = solve_day9_part1(&data);
         assert_eq!(result, 15);
     }
 
     #[test]
     fn test_day9_part2() {
-        let data = input_generator_day9(&DATA);
+        let data = input_generator_day9(DATA);
         let result = solve_day9_part2(&data);
         assert_eq!(result, 1134);
     }
+
+    #[test]
+    fn test_basin_report() {
+        let mut data = input_generator_day9(DATA);
+        data.fill_basin_ids();
+        let report = data.basin_report();
+        assert_eq!(report.basins.len(), 4);
+        assert_eq!(
+            report.basins[0],
+            Basin {
+                id: 0,
+                low_point: (1, 0),
+                low_value: 1,
+                size: 3,
+                bounds: BoundingBox {
+                    min_x: 0,
+                    min_y: 0,
+                    max_x: 1,
+                    max_y: 1
+                }
+            }
+        );
+        assert_eq!(
+            report.largest(3).iter().map(|b| b.size).collect_vec(),
+            vec![14, 9, 9]
+        );
+        assert_eq!(report.top_k_product(4), Some(3 * 9 * 14 * 9));
+        assert_eq!(report.top_k_product(5), None);
+    }
+
+    #[test]
+    fn test_adjacent_positions() {
+        let data = input_generator_day9(DATA);
+        assert_eq!(data.get_adjacent_positions(0, 0), vec![(1, 0), (0, 1)]);
+
+        let eight = data.clone().with_neighbourhood(Neighbourhood::Eight);
+        assert_eq!(eight.get_adjacent_positions(0, 0).len(), 3);
+        assert_eq!(eight.get_adjacent_positions(4, 2).len(), 8);
+
+        let hex = data.clone().with_neighbourhood(Neighbourhood::Hex);
+        assert_eq!(
+            hex.get_adjacent_positions(4, 2),
+            vec![(3, 2), (5, 2), (3, 1), (4, 1), (3, 3), (4, 3)]
+        );
+        assert_eq!(
+            hex.get_adjacent_positions(4, 1),
+            vec![(3, 1), (5, 1), (4, 0), (5, 0), (4, 2), (5, 2)]
+        );
+
+        let torus = data.with_boundary(Boundary::Wrapping);
+        assert_eq!(
+            torus.get_adjacent_positions(0, 0),
+            vec![(9, 0), (0, 4), (1, 0), (0, 1)]
+        );
+    }
+
+    #[test]
+    fn test_hex_wrapping_is_symmetric() {
+        let map = input_generator_day9("1234\n5678\n1234\n5678")
+            .with_neighbourhood(Neighbourhood::Hex)
+            .with_boundary(Boundary::Wrapping);
+        for cell in &map {
+            for (ax, ay) in map.get_adjacent_positions(cell.x, cell.y) {
+                assert!(map
+                    .get_adjacent_positions(ax, ay)
+                    .contains(&(cell.x, cell.y)));
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "even map height")]
+    fn test_hex_wrapping_rejects_odd_height() {
+        input_generator_day9(DATA)
+            .with_boundary(Boundary::Wrapping)
+            .with_neighbourhood(Neighbourhood::Hex);
+    }
+
+    #[test]
+    fn test_topology_low_points() {
+        // the 3 is only a low point if the diagonal 2 is not a neighbour
+        let data = input_generator_day9("39\n92");
+        assert_eq!(solve_day9_part1(&data), 4 + 3);
+        let eight = data.with_neighbourhood(Neighbourhood::Eight);
+        assert!(!eight.is_local_min(eight.get_cell(0, 0)));
+        assert_eq!(solve_day9_part1(&eight), 3);
+
+        // wrapping makes the 6 at the bottom edge a neighbour of the 5 at (6, 4)...
+        let data = input_generator_day9(DATA);
+        let torus = data.with_boundary(Boundary::Wrapping);
+        assert_eq!(solve_day9_part1(&torus), 2 + 1 + 6);
+        // ...and joins all four basins into one through the edges
+        let mut map = torus;
+        map.fill_basin_ids();
+        let report = map.basin_report();
+        assert_eq!(report.basins.len(), 1);
+        assert_eq!(report.basins[0].size, 35);
+    }
+
+    #[test]
+    fn test_stream_risk_sum() {
+        assert_eq!(stream_risk_sum(DATA.as_bytes()).unwrap(), 15);
+        assert_eq!(
+            stream_risk_sum("2199943210\r\n3987894921\n\n".as_bytes()).unwrap(),
+            2 + 1 + 8
+        );
+        assert_eq!(stream_risk_sum("5".as_bytes()).unwrap(), 6);
+        assert_eq!(stream_risk_sum("".as_bytes()).unwrap(), 0);
+        assert!(stream_risk_sum("12\n1x".as_bytes()).is_err());
+        assert!(stream_risk_sum("12\n123".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_stream_matches_map() {
+        let mut rng = SplitMix64::new(9);
+        let input = (0..40)
+            .map(|_| {
+                (0..60)
+                    .map(|_| (b'0' + rng.below(10) as u8) as char)
+                    .collect::<String>()
+            })
+            .join("\n");
+        let expected = solve_day9_part1(&input_generator_day9(&input));
+        assert_eq!(
+            stream_risk_sum(io::BufReader::new(input.as_bytes())).unwrap(),
+            expected as u64
+        );
+    }
+
+    #[test]
+    fn test_basin_colours() {
+        let colours = (0..50).map(basin_colour).collect_vec();
+        assert_eq!(colours.iter().unique().count(), 50);
+        assert!(!colours.contains(&WALL_COLOUR));
+        assert!(!colours.contains(&LOW_POINT_COLOUR));
+    }
+
+    #[test]
+    fn test_render_ansi() {
+        let mut data = input_generator_day9("19\n29");
+        data.fill_basin_ids();
+        let (r, g, b) = basin_colour(0);
+        let expected = format!(
+            "\x1b[30;48;2;255;255;255m1\x1b[97;48;2;0;0;0m9\x1b[0m\n\
+             \x1b[{};48;2;{};{};{}m2\x1b[97;48;2;0;0;0m9\x1b[0m\n",
+            text_colour((r, g, b)),
+            r,
+            g,
+            b
+        );
+        assert_eq!(data.render_ansi(), expected);
+    }
+
+    #[test]
+    fn test_write_ppm() {
+        let mut data = input_generator_day9(DATA);
+        data.fill_basin_ids();
+        let mut out = vec![];
+        data.write_ppm(&mut out, 2).unwrap();
+
+        let header = b"P6\n20 10\n255\n";
+        assert_eq!(&out[..header.len()], header);
+        let pixels = &out[header.len()..];
+        assert_eq!(pixels.len(), 20 * 10 * 3);
+        let pixel = |x: usize, y: usize| {
+            let i = (y * 20 + x) * 3;
+            (pixels[i], pixels[i + 1], pixels[i + 2])
+        };
+        // (1, 0) is a low point, (2, 0) a wall, (0, 0) in basin 0, (8, 1) in basin 1
+        assert_eq!(pixel(2, 0), LOW_POINT_COLOUR);
+        assert_eq!(pixel(3, 1), LOW_POINT_COLOUR);
+        assert_eq!(pixel(4, 0), WALL_COLOUR);
+        assert_eq!(pixel(1, 1), basin_colour(0));
+        assert_eq!(pixel(16, 2), basin_colour(1));
+    }
+
+    #[test]
+    fn test_wall_height() {
+        let data = input_generator_day9(DATA).with_wall_height(8);
+        let mut map = data.clone();
+        map.fill_basin_ids();
+        let report = map.basin_report();
+        assert_eq!(
+            report.basins.iter().map(|b| b.size).collect_vec(),
+            vec![3, 9, 7, 6]
+        );
+        assert!(map
+            .into_iter()
+            .all(|c| (c.basin_id == -1) == (c.value >= 8)));
+
+        // refilling with a lower wall height clears the ids of the new walls
+        let mut refilled = input_generator_day9(DATA);
+        refilled.fill_basin_ids();
+        let mut refilled = refilled.with_wall_height(8);
+        refilled.fill_basin_ids();
+        assert_eq!(refilled.basin_report(), report);
+    }
+
+    #[test]
+    fn test_basin_report_sparse_ids() {
+        let mut map = input_generator_day9("12\n34");
+        map.set_basin_id(0, 0, 7);
+        map.set_basin_id(1, 1, 2);
+        map.set_basin_id(1, 0, 7);
+        let report = map.basin_report();
+        assert_eq!(report.basins.iter().map(|b| b.id).collect_vec(), vec![2, 7]);
+        assert_eq!(report.basins[1].size, 2);
+        assert_eq!(report.basins[0].low_point, (1, 1));
+    }
+
+    #[test]
+    fn test_watershed_matches_walls() {
+        // every basin of the example has a single low point, so watersheds change nothing
+        let mut data = input_generator_day9(DATA);
+        let report = data.fill_watershed_ids();
+        assert_eq!(report.conflicts, vec![]);
+        assert_eq!(data.basin_report().top_k_product(3), Some(1134));
+    }
+
+    #[test]
+    fn test_watershed_splits_basins() {
+        // one wall-bounded region with two low points and a ridge between them
+        let mut data = input_generator_day9("13531\n24642").with_wall_height(10);
+        let report = data.fill_watershed_ids();
+        let ids = data.into_iter().map(|c| c.basin_id).collect_vec();
+        assert_eq!(ids, vec![0, 0, 0, 1, 1, 0, 0, 0, 1, 1]);
+        assert_eq!(
+            report.conflicts,
+            vec![
+                DrainConflict {
+                    cell: (2, 0),
+                    low_points: vec![(0, 0), (4, 0)]
+                },
+                DrainConflict {
+                    cell: (2, 1),
+                    low_points: vec![(0, 0), (4, 0)]
+                }
+            ]
+        );
+        let sizes = data
+            .basin_report()
+            .basins
+            .iter()
+            .map(|b| b.size)
+            .collect_vec();
+        assert_eq!(sizes, vec![6, 4]);
+
+        let mut walled = input_generator_day9("13531\n24642").with_wall_height(10);
+        walled.fill_basin_ids();
+        assert_eq!(walled.basin_report().basins.len(), 1);
+    }
+
+    #[test]
+    fn test_watershed_plateaus() {
+        // the flat 2s drain through their only exit; the flat 1s are one low point
+        let mut data = input_generator_day9("2221\n9991\n0999");
+        let report = data.fill_watershed_ids();
+        assert_eq!(report.conflicts, vec![]);
+        let ids = data.into_iter().map(|c| c.basin_id).collect_vec();
+        assert_eq!(ids, vec![0, 0, 0, 0, -1, -1, -1, 0, 1, -1, -1, -1]);
+    }
+
+    #[test]
+    fn test_basins_without_strict_low_point() {
+        // the first basin bottoms out in a plateau of two 1s and has id 0
+        let data = input_generator_day9("1192\n2392\n9999\n0909");
+        let mut map = data.clone();
+        map.fill_basin_ids();
+        let report = map.basin_report();
+        assert_eq!(report.basins.len(), 4);
+        assert_eq!(report.basins[0].size, 4);
+        assert_eq!(report.basins[0].low_point, (0, 0));
+        assert_eq!(report.top_k_product(3), Some(8));
+        assert_eq!(solve_day9_part2(&data), 8);
+    }
 }
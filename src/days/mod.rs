@@ -1,11 +1,11 @@
 mod day1;
-mod day10;
-mod day11;
+pub mod day10;
+pub mod day11;
 mod day2;
 mod day3;
 mod day4;
 mod day5;
-mod day6;
-mod day7;
-mod day8;
-mod day9;
+pub mod day6;
+pub mod day7;
+pub mod day8;
+pub mod day9;